@@ -0,0 +1,548 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Internet checksum verification.
+//!
+//! Checksums are recomputed from the frame rather than from the parsed
+//! headers, so that IPv4 options, IPv6 extension headers and payloads are
+//! covered.
+
+use crate::decode::ipv6_upper_layer;
+use crate::{
+    ethernet_h, geneve_h, headers_t, ipv6_h, sidecar_h, udp_h, vlan_h,
+};
+use bitvec::prelude::*;
+use p4rs::Header;
+
+/// A checksum found in a frame along with the value it should have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub found: u16,
+    pub expected: u16,
+}
+
+impl Check {
+    pub fn is_bad(&self) -> bool {
+        self.found != self.expected
+    }
+}
+
+/// The checksums of a frame. A checksum is `None` when its header is absent,
+/// or when it cannot be verified, e.g. because the frame was truncated on
+/// capture or the transport header is in a fragment.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Checksums {
+    pub ipv4: Option<Check>,
+    pub icmp: Option<Check>,
+    pub tcp: Option<Check>,
+    pub udp: Option<Check>,
+    pub inner_ipv4: Option<Check>,
+    pub inner_icmp: Option<Check>,
+    pub inner_tcp: Option<Check>,
+    pub inner_udp: Option<Check>,
+}
+
+impl Checksums {
+    pub fn new(h: &headers_t, frame: &[u8]) -> Self {
+        let (outer, inner) = layers(h, frame);
+        let pick =
+            |l: &Layer, t: Transport| l.transport.filter(|_| l.kind == Some(t));
+        Self {
+            ipv4: outer.ip,
+            icmp: pick(&outer, Transport::Icmp),
+            tcp: pick(&outer, Transport::Tcp),
+            udp: pick(&outer, Transport::Udp),
+            inner_ipv4: inner.ip,
+            inner_icmp: pick(&inner, Transport::Icmp),
+            inner_tcp: pick(&inner, Transport::Tcp),
+            inner_udp: pick(&inner, Transport::Udp),
+        }
+    }
+
+    /// Whether any checksum that could be verified is wrong.
+    pub fn is_bad(&self) -> bool {
+        [
+            self.ipv4,
+            self.icmp,
+            self.tcp,
+            self.udp,
+            self.inner_ipv4,
+            self.inner_icmp,
+            self.inner_tcp,
+            self.inner_udp,
+        ]
+        .iter()
+        .flatten()
+        .any(Check::is_bad)
+    }
+}
+
+/// Locate the outer and, for Geneve encapsulated frames, the inner network
+/// layer in `frame`.
+fn layers(h: &headers_t, frame: &[u8]) -> (Layer, Layer) {
+    let mut off = 0;
+    if h.ethernet.isValid() {
+        off += len::<ethernet_h>();
+    }
+    if h.vlan.isValid() {
+        off += len::<vlan_h>();
+    }
+    if h.sidecar.isValid() {
+        off += len::<sidecar_h>();
+    }
+    let transport =
+        Transport::of(h.icmp.isValid(), h.tcp.isValid(), h.udp.isValid());
+    let outer =
+        Layer::new(frame, off, h.ipv4.isValid(), h.ipv6.isValid(), transport);
+
+    let mut inner = Layer::default();
+    if h.geneve.isValid() && h.inner_eth.isValid() {
+        let olen: u8 = h.geneve.opt_len.load();
+        let off = outer.transport_off
+            + len::<udp_h>()
+            + len::<geneve_h>()
+            + ((olen as usize) << 2)
+            + len::<ethernet_h>();
+        let transport = Transport::of(
+            h.inner_icmp.isValid(),
+            h.inner_tcp.isValid(),
+            h.inner_udp.isValid(),
+        );
+        inner = Layer::new(
+            frame,
+            off,
+            h.inner_ipv4.isValid(),
+            h.inner_ipv6.isValid(),
+            transport,
+        );
+    }
+    (outer, inner)
+}
+
+fn len<H: Header>() -> usize {
+    H::size() >> 3
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transport {
+    Icmp,
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    fn of(icmp: bool, tcp: bool, udp: bool) -> Option<Self> {
+        match (icmp, tcp, udp) {
+            (true, _, _) => Some(Self::Icmp),
+            (_, true, _) => Some(Self::Tcp),
+            (_, _, true) => Some(Self::Udp),
+            _ => None,
+        }
+    }
+
+    /// Offset of the checksum within the transport header.
+    fn checksum_off(&self) -> usize {
+        match self {
+            Self::Icmp => 2,
+            Self::Tcp => 16,
+            Self::Udp => 6,
+        }
+    }
+}
+
+/// The checksums of a network layer header and of the transport header it
+/// carries, along with where the transport header is.
+#[derive(Default)]
+struct Layer {
+    ip: Option<Check>,
+    kind: Option<Transport>,
+    transport: Option<Check>,
+    /// Offset of the transport header in the frame.
+    transport_off: usize,
+}
+
+impl Layer {
+    fn new(
+        frame: &[u8],
+        off: usize,
+        v4: bool,
+        v6: bool,
+        kind: Option<Transport>,
+    ) -> Self {
+        let mut layer = Self {
+            kind,
+            transport_off: off,
+            ..Default::default()
+        };
+        // The pseudo header sum without the protocol, the start and end of
+        // the transport segment, and whether it is in a single fragment.
+        let (pseudo, proto, start, end, whole) = if v4 {
+            let Some(hdr) = frame.get(off..off + 20) else {
+                return layer;
+            };
+            let ihl = ((hdr[0] & 0xf) as usize) << 2;
+            let total = u16::from_be_bytes([hdr[2], hdr[3]]) as usize;
+            let frag = u16::from_be_bytes([hdr[6], hdr[7]]) & 0x3fff;
+            layer.ip = frame.get(off..off + ihl).and_then(|h| check(0, h, 10));
+            let len = total.saturating_sub(ihl) as u32;
+            let pseudo = sum(&hdr[12..20]) + len;
+            let proto = match kind {
+                Some(Transport::Icmp) => None,
+                _ => Some(hdr[9]),
+            };
+            (pseudo, proto, off + ihl, off + total, frag == 0)
+        } else if v6 {
+            let Some(hdr) = frame.get(off..off + len::<ipv6_h>()) else {
+                return layer;
+            };
+            let payload = u16::from_be_bytes([hdr[4], hdr[5]]) as usize;
+            let start = off + hdr.len();
+            let end = start + payload;
+            let rest = frame.get(start..).unwrap_or_default();
+            let Some((ext, next, whole)) = ipv6_upper_layer(hdr[6], rest)
+            else {
+                return layer;
+            };
+            let len = payload.saturating_sub(ext) as u32;
+            let pseudo = sum(&hdr[8..40]) + (len >> 16) + (len & 0xffff);
+            (pseudo, Some(next), start + ext, end, whole)
+        } else {
+            return layer;
+        };
+        layer.transport_off = start;
+
+        let Some(kind) = kind else { return layer };
+        let Some(seg) = frame.get(start..end).filter(|_| whole) else {
+            return layer;
+        };
+        // ICMP over IPv4 is the only checksum without a pseudo header.
+        let initial = match proto {
+            Some(proto) => pseudo + u32::from(proto),
+            None => 0,
+        };
+        let mut c = check(initial, seg, kind.checksum_off());
+        if kind == Transport::Udp {
+            c = c.and_then(|c| match c {
+                // A zero UDP checksum over IPv4 means there is none.
+                Check { found: 0, .. } if v4 => None,
+                // A computed zero is sent as all ones.
+                Check { expected: 0, .. } => Some(Check {
+                    expected: 0xffff,
+                    ..c
+                }),
+                c => Some(c),
+            });
+        }
+        layer.transport = c;
+        layer
+    }
+}
+
+/// Ones' complement sum of `data` as big endian 16 bit words, unfolded.
+fn sum(data: &[u8]) -> u32 {
+    data.chunks(2)
+        .map(|w| u32::from(w[0]) << 8 | u32::from(*w.get(1).unwrap_or(&0)))
+        .sum()
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Check the checksum at `at` in `data`, adding `initial` to the sum.
+fn check(initial: u32, data: &[u8], at: usize) -> Option<Check> {
+    let found = data.get(at..at + 2)?;
+    let found = u16::from_be_bytes([found[0], found[1]]);
+    let expected = !fold(initial + sum(data) - u32::from(found));
+    Some(Check { found, expected })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TCP: u8 = 6;
+    const UDP: u8 = 17;
+    const ICMP: u8 = 1;
+    const FRAGMENT: u8 = 44;
+    const HBH: u8 = 0;
+
+    /// The ones' complement of the ones' complement sum, as in RFC 1071.
+    fn reference(data: &[u8]) -> u16 {
+        let mut sum = 0u32;
+        for w in data.chunks(2) {
+            sum +=
+                u32::from(u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)]));
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    fn ipv4(proto: u8, frag: u16, payload: &[u8]) -> Vec<u8> {
+        let total = (20 + payload.len()) as u16;
+        let mut p = vec![0x45, 0];
+        p.extend(total.to_be_bytes());
+        p.extend([0, 1]);
+        p.extend(frag.to_be_bytes());
+        p.extend([64, proto, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        let c = reference(&p);
+        p[10..12].copy_from_slice(&c.to_be_bytes());
+        p.extend(payload);
+        p
+    }
+
+    fn ipv6(next: u8, payload: &[u8]) -> Vec<u8> {
+        let mut p = vec![0x60, 0, 0, 0];
+        p.extend((payload.len() as u16).to_be_bytes());
+        p.extend([next, 64]);
+        p.extend("fd00::1".parse::<std::net::Ipv6Addr>().unwrap().octets());
+        p.extend("fd00::2".parse::<std::net::Ipv6Addr>().unwrap().octets());
+        p.extend(payload);
+        p
+    }
+
+    /// The pseudo header for a segment of `len` octets carried in `ip`.
+    fn pseudo(ip: &[u8], proto: u8, len: usize) -> Vec<u8> {
+        if ip[0] >> 4 == 4 {
+            let mut p = ip[12..20].to_vec();
+            p.extend([0, proto]);
+            p.extend((len as u16).to_be_bytes());
+            p
+        } else {
+            let mut p = ip[8..40].to_vec();
+            p.extend((len as u32).to_be_bytes());
+            p.extend([0, 0, 0, proto]);
+            p
+        }
+    }
+
+    /// A segment with a correct checksum at `at`, given the addresses of the
+    /// network layer header `ip` it will be carried in.
+    fn seal(ip: &[u8], proto: u8, mut seg: Vec<u8>, at: usize) -> Vec<u8> {
+        let c =
+            reference(&[pseudo(ip, proto, seg.len()), seg.clone()].concat());
+        seg[at..at + 2].copy_from_slice(&c.to_be_bytes());
+        seg
+    }
+
+    fn tcp() -> Vec<u8> {
+        let mut seg = vec![0x30, 0x39, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0];
+        seg.extend([0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        // An odd length, so the last octet is padded.
+        seg.extend(b"hello");
+        seg
+    }
+
+    fn udp(payload: &[u8]) -> Vec<u8> {
+        let mut seg = vec![0x30, 0x39, 0, 53];
+        seg.extend(((8 + payload.len()) as u16).to_be_bytes());
+        seg.extend([0, 0]);
+        seg.extend(payload);
+        seg
+    }
+
+    fn v4_layer(frame: &[u8], kind: Transport) -> Layer {
+        Layer::new(frame, 0, true, false, Some(kind))
+    }
+
+    fn v6_layer(frame: &[u8], kind: Transport) -> Layer {
+        Layer::new(frame, 0, false, true, Some(kind))
+    }
+
+    fn good(c: Option<Check>) -> bool {
+        matches!(c, Some(c) if !c.is_bad())
+    }
+
+    fn bad(c: Option<Check>) -> bool {
+        matches!(c, Some(c) if c.is_bad())
+    }
+
+    #[test]
+    fn ipv4_header() {
+        let hdr = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8,
+            0x61, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        let layer = Layer::new(&hdr, 0, true, false, None);
+        assert_eq!(
+            layer.ip,
+            Some(Check {
+                found: 0xb861,
+                expected: 0xb861
+            })
+        );
+
+        let mut corrupt = hdr;
+        corrupt[8] = 0x3f;
+        assert!(bad(Layer::new(&corrupt, 0, true, false, None).ip));
+
+        // Options are covered.
+        let mut opts = ipv4(UDP, 0, &[]);
+        opts[0] = 0x46;
+        opts.extend([1, 1, 1, 0]);
+        opts[10..12].fill(0);
+        let c = reference(&opts);
+        opts[10..12].copy_from_slice(&c.to_be_bytes());
+        assert!(good(Layer::new(&opts, 0, true, false, None).ip));
+        opts[22] = 2;
+        assert!(bad(Layer::new(&opts, 0, true, false, None).ip));
+    }
+
+    #[test]
+    fn tcp_and_udp_over_ipv4() {
+        let ip = ipv4(TCP, 0, &[]);
+        let frame = ipv4(TCP, 0, &seal(&ip, TCP, tcp(), 16));
+        let layer = v4_layer(&frame, Transport::Tcp);
+        assert!(good(layer.ip));
+        assert!(good(layer.transport));
+        assert_eq!(layer.transport_off, 20);
+
+        let mut corrupt = frame.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(bad(v4_layer(&corrupt, Transport::Tcp).transport));
+
+        let ip = ipv4(UDP, 0, &[]);
+        let frame = ipv4(UDP, 0, &seal(&ip, UDP, udp(b"query"), 6));
+        assert!(good(v4_layer(&frame, Transport::Udp).transport));
+    }
+
+    #[test]
+    fn tcp_and_udp_over_ipv6() {
+        let ip = ipv6(TCP, &[]);
+        let frame = ipv6(TCP, &seal(&ip, TCP, tcp(), 16));
+        let layer = v6_layer(&frame, Transport::Tcp);
+        assert_eq!(layer.ip, None);
+        assert!(good(layer.transport));
+        assert_eq!(layer.transport_off, 40);
+
+        let ip = ipv6(UDP, &[]);
+        let mut frame = ipv6(UDP, &seal(&ip, UDP, udp(b"query"), 6));
+        assert!(good(v6_layer(&frame, Transport::Udp).transport));
+        // The addresses are part of the pseudo header.
+        frame[39] ^= 1;
+        assert!(bad(v6_layer(&frame, Transport::Udp).transport));
+    }
+
+    #[test]
+    fn ipv6_extension_headers() {
+        let ip = ipv6(UDP, &[]);
+        let seg = seal(&ip, UDP, udp(b"query"), 6);
+        // The pseudo header length leaves the extension headers out.
+        let hbh = [UDP, 0, 1, 4, 0, 0, 0, 0];
+        let frame = ipv6(HBH, &[&hbh[..], &seg].concat());
+        let layer = v6_layer(&frame, Transport::Udp);
+        assert!(good(layer.transport));
+        assert_eq!(layer.transport_off, 48);
+    }
+
+    #[test]
+    fn udp_zero_and_all_ones() {
+        // No checksum over IPv4.
+        let frame = ipv4(UDP, 0, &udp(b"query"));
+        assert_eq!(v4_layer(&frame, Transport::Udp).transport, None);
+        // It is mandatory over IPv6.
+        let frame = ipv6(UDP, &udp(b"query"));
+        assert!(bad(v6_layer(&frame, Transport::Udp).transport));
+
+        // A payload word that makes the sum all ones, so that the computed
+        // checksum is zero and is sent as all ones.
+        for v4 in [true, false] {
+            let wrap = |seg: &[u8]| {
+                if v4 {
+                    ipv4(UDP, 0, seg)
+                } else {
+                    ipv6(UDP, seg)
+                }
+            };
+            let seg = udp(&[0, 0]);
+            let ip = wrap(&[]);
+            let sum = !reference(&[pseudo(&ip, UDP, seg.len()), seg].concat());
+            let mut seg = udp(&(0xffff - sum).to_be_bytes());
+            seg[6..8].fill(0xff);
+            let frame = wrap(&seg);
+            let layer = Layer::new(&frame, 0, v4, !v4, Some(Transport::Udp));
+            assert_eq!(
+                layer.transport,
+                Some(Check {
+                    found: 0xffff,
+                    expected: 0xffff
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn icmp_pseudo_header() {
+        // ICMP over IPv4 has no pseudo header.
+        let mut echo = vec![8, 0, 0, 0, 0, 1, 0, 1];
+        echo.extend(b"ping");
+        let c = reference(&echo);
+        echo[2..4].copy_from_slice(&c.to_be_bytes());
+        let frame = ipv4(ICMP, 0, &echo);
+        assert!(good(v4_layer(&frame, Transport::Icmp).transport));
+
+        // ICMPv6 does, this neighbor advertisement is from a capture.
+        let frame = [
+            0x60, 0x00, 0x00, 0x00, 0x00, 0x20, 0x3a, 0xff, 0xfe, 0x80, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, 0x40, 0x25, 0xff, 0xfe, 0x04,
+            0x01, 0x1a, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xaa,
+            0x40, 0x25, 0xff, 0xfe, 0x05, 0x04, 0x19, 0x88, 0x00, 0x5a, 0xa5,
+            0xe0, 0x00, 0x00, 0x00, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0xaa, 0x40, 0x25, 0xff, 0xfe, 0x04, 0x01, 0x1a, 0x02, 0x01,
+            0xa8, 0x40, 0x25, 0x04, 0x01, 0x1a,
+        ];
+        assert!(good(v6_layer(&frame, Transport::Icmp).transport));
+        let mut corrupt = frame;
+        corrupt[8] ^= 1;
+        assert!(bad(v6_layer(&corrupt, Transport::Icmp).transport));
+    }
+
+    #[test]
+    fn fragments() {
+        let ip = ipv4(UDP, 0, &[]);
+        let seg = seal(&ip, UDP, udp(b"query"), 6);
+        // More fragments, then a later fragment.
+        for frag in [0x2000, 0x0001] {
+            let layer = v4_layer(&ipv4(UDP, frag, &seg), Transport::Udp);
+            assert!(good(layer.ip));
+            assert_eq!(layer.transport, None);
+        }
+        // Don't fragment is not a fragment.
+        let layer = v4_layer(&ipv4(UDP, 0x4000, &seg), Transport::Udp);
+        assert!(good(layer.transport));
+
+        let ip = ipv6(UDP, &[]);
+        let seg = seal(&ip, UDP, udp(b"query"), 6);
+        let first = [UDP, 0, 0, 1, 0, 0, 0, 7];
+        let frame = ipv6(FRAGMENT, &[&first[..], &seg].concat());
+        assert_eq!(v6_layer(&frame, Transport::Udp).transport, None);
+        // An atomic fragment holds the whole packet.
+        let atomic = [UDP, 0, 0, 0, 0, 0, 0, 7];
+        let frame = ipv6(FRAGMENT, &[&atomic[..], &seg].concat());
+        assert!(good(v6_layer(&frame, Transport::Udp).transport));
+    }
+
+    #[test]
+    fn truncated() {
+        let ip = ipv4(TCP, 0, &[]);
+        let frame = ipv4(TCP, 0, &seal(&ip, TCP, tcp(), 16));
+        let layer = v4_layer(&frame[..frame.len() - 1], Transport::Tcp);
+        assert!(good(layer.ip));
+        assert_eq!(layer.transport, None);
+        let layer = v4_layer(&frame[..19], Transport::Tcp);
+        assert_eq!(layer.ip, None);
+        assert_eq!(layer.transport, None);
+
+        let ip = ipv6(TCP, &[]);
+        let frame = ipv6(TCP, &seal(&ip, TCP, tcp(), 16));
+        let layer = v6_layer(&frame[..frame.len() - 1], Transport::Tcp);
+        assert_eq!(layer.transport, None);
+        // An extension header cut short.
+        let frame = ipv6(HBH, &[TCP, 1, 0, 0]);
+        assert_eq!(v6_layer(&frame, Transport::Tcp).transport, None);
+    }
+}
@@ -1,8 +1,9 @@
 // Copyright 2023 Oxide Computer Company
 
 use crate::dump::{Alp, Ethertype, IpProto};
-use clap::{Parser, Subcommand};
-use std::net::IpAddr;
+use crate::filter::{GeneveOpt, IpPrefix, MacMatch, PortRange, VniRange};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
 
 pub fn get_styles() -> clap::builder::Styles {
     clap::builder::Styles::styled()
@@ -42,6 +43,10 @@ pub enum Command {
 
     /// Read and display packets in hex format.
     HexRead(HexRead),
+
+    /// Read and display packets from a pcap or pcapng file.
+    #[command(alias = "read")]
+    PcapRead(PcapRead),
 }
 
 #[derive(Parser, Debug)]
@@ -54,37 +59,114 @@ pub struct Snoop {
     #[arg(long)]
     pub hex: bool,
 
+    /// How to show frames.
+    #[arg(long, value_enum, default_value_t, conflicts_with = "enabled")]
+    pub output: OutputFormat,
+
+    /// Show capture times in text output, in the provided format.
+    #[arg(long, value_enum)]
+    pub time: Option<TimeFormat>,
+
+    /// Write frames that pass the filters to the provided pcapng file.
+    #[arg(long)]
+    pub write: Option<String>,
+
+    /// Start a new output file once the current one reaches the provided size
+    /// in megabytes.
+    #[arg(
+        long,
+        requires = "write",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub rotate_size: Option<u64>,
+
+    /// Keep at most the provided number of output files, overwriting the
+    /// oldest one once the limit is reached.
+    #[arg(
+        long,
+        requires = "rotate_size",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub rotate_count: Option<u64>,
+
+    #[command(flatten)]
+    pub filter: Filter,
+    #[command(flatten)]
+    pub follow: Follow,
+    #[command(flatten)]
+    pub stop: Stop,
+}
+
+#[derive(Args, Debug, Default)]
+#[command(next_help_heading = "Filters")]
+pub struct Filter {
+    /// Filter expression, e.g. 'ip.src == 10.0.0.1 and not tcp.port 22'.
+    /// Comparisons on eth.src, eth.dst, eth.addr, eth.type, vlan.id, ip.src,
+    /// ip.dst, ip.addr, ip.proto, [tcp.|udp.]port, [tcp.|udp.]srcport,
+    /// [tcp.|udp.]dstport, geneve.vni, geneve.opt and alp can be combined
+    /// with and, or, not and parentheses. Prefix a field with inner. to match
+    /// encapsulated packets. Combined with the other filter flags.
+    #[arg(long = "filter", value_name = "EXPR")]
+    pub expr: Option<String>,
+
     /// Filter on the provided ethernet packet type.
     #[arg(long)]
     pub eth_type: Option<Ethertype>,
 
-    /// Filter on the provided source IPs.
+    /// Filter on the provided source MACs. Accepts MAC prefixes such as
+    /// a8:40:25:00:00:00/24 and the broadcast and multicast keywords.
+    #[arg(long)]
+    pub eth_src: Vec<MacMatch>,
+
+    /// Filter on the provided destination MACs, MAC prefixes, broadcast or
+    /// multicast.
+    #[arg(long)]
+    pub eth_dst: Vec<MacMatch>,
+
+    /// Filter on the provided MACs, MAC prefixes, broadcast or multicast for
+    /// source or destination.
+    #[arg(long)]
+    pub eth_host: Vec<MacMatch>,
+
+    /// Filter on the provided source IPs or CIDR prefixes.
     #[arg(long)]
-    pub ip_src: Vec<IpAddr>,
+    pub ip_src: Vec<IpPrefix>,
 
-    /// Filter on the provided destination IPs.
+    /// Filter on the provided destination IPs or CIDR prefixes.
     #[arg(long)]
-    pub ip_dst: Vec<IpAddr>,
+    pub ip_dst: Vec<IpPrefix>,
 
-    /// Filter on the provided IPs for source or destination.
+    /// Filter on the provided IPs or CIDR prefixes for source or destination.
     #[arg(long)]
-    pub ip_host: Vec<IpAddr>,
+    pub ip_host: Vec<IpPrefix>,
 
-    /// Filter on the provided IP protocol types.
+    /// Filter on the provided IP protocol types. For IPv6 this is the upper
+    /// layer protocol, after any extension headers.
     #[arg(long)]
     pub ip_proto: Vec<IpProto>,
 
-    /// Filter on transport layer source port.
+    /// Filter on transport layer source ports or port ranges, e.g. 1024-2047.
+    #[arg(long)]
+    pub src_port: Vec<PortRange>,
+
+    /// Filter on transport layer destination ports or port ranges.
     #[arg(long)]
-    pub src_port: Vec<u16>,
+    pub dst_port: Vec<PortRange>,
 
-    /// Filter on transport layer destination port.
+    /// Filter on transport layer ports or port ranges.
     #[arg(long)]
-    pub dst_port: Vec<u16>,
+    pub port: Vec<PortRange>,
 
-    /// Filter on transport layer port.
+    /// Filter on Geneve VNIs or VNI ranges, e.g. 100-199. Accepts a comma
+    /// separated list.
+    #[arg(long, value_delimiter = ',')]
+    pub vni: Vec<VniRange>,
+
+    /// Filter on Geneve options, written as class:type[:value], e.g.
+    /// oxide:external. Any of the first four options of a frame can match.
+    /// The value is compared with the first word of the option data.
     #[arg(long)]
-    pub port: Vec<u16>,
+    pub geneve_opt: Vec<GeneveOpt>,
 
     /// Filter on the provided application layer protocol types.
     #[arg(long)]
@@ -114,34 +196,53 @@ pub struct Snoop {
     #[arg(long)]
     pub inner_eth_type: Vec<Ethertype>,
 
-    /// Filter on the provided source IPs for encapsulated packets.
+    /// Filter on the provided source MACs, MAC prefixes, broadcast or
+    /// multicast for encapsulated packets.
+    #[arg(long)]
+    pub inner_eth_src: Vec<MacMatch>,
+
+    /// Filter on the provided destination MACs, MAC prefixes, broadcast or
+    /// multicast for encapsulated packets.
     #[arg(long)]
-    pub inner_ip_src: Vec<IpAddr>,
+    pub inner_eth_dst: Vec<MacMatch>,
 
-    /// Filter on the provided destination IPs for encapsulated packets.
+    /// Filter on the provided MACs, MAC prefixes, broadcast or multicast for
+    /// source or destination for encapsulated packets.
     #[arg(long)]
-    pub inner_ip_dst: Vec<IpAddr>,
+    pub inner_eth_host: Vec<MacMatch>,
 
-    /// Filter on the provided IPs for source or destination for encapsulated
+    /// Filter on the provided source IPs or CIDR prefixes for encapsulated
     /// packets.
     #[arg(long)]
-    pub inner_ip_host: Vec<IpAddr>,
+    pub inner_ip_src: Vec<IpPrefix>,
+
+    /// Filter on the provided destination IPs or CIDR prefixes for
+    /// encapsulated packets.
+    #[arg(long)]
+    pub inner_ip_dst: Vec<IpPrefix>,
+
+    /// Filter on the provided IPs or CIDR prefixes for source or destination
+    /// for encapsulated packets.
+    #[arg(long)]
+    pub inner_ip_host: Vec<IpPrefix>,
 
     /// Filter on the provided IP protocol types for encapsulated packets.
     #[arg(long)]
     pub inner_ip_proto: Vec<IpProto>,
 
-    /// Filter on transport layer source port for encapsulated packets.
+    /// Filter on transport layer source ports or port ranges for encapsulated
+    /// packets.
     #[arg(long)]
-    pub inner_src_port: Vec<u16>,
+    pub inner_src_port: Vec<PortRange>,
 
-    /// Filter on transport layer destination port for encapsulated packets.
+    /// Filter on transport layer destination ports or port ranges for
+    /// encapsulated packets.
     #[arg(long)]
-    pub inner_dst_port: Vec<u16>,
+    pub inner_dst_port: Vec<PortRange>,
 
-    /// Filter on transport layer port for encapsulated packets.
+    /// Filter on transport layer ports or port ranges for encapsulated packets.
     #[arg(long)]
-    pub inner_port: Vec<u16>,
+    pub inner_port: Vec<PortRange>,
 
     /// Filter on the provided application layer protocol types for encapsulated
     /// packets.
@@ -159,11 +260,105 @@ pub struct Snoop {
     /// Shorthand for --inner-eth-type arp
     #[arg(long)]
     pub inner_arp: bool,
+
+    /// Only show frames with a checksum that does not verify, e.g. to find
+    /// checksum offload bugs.
+    #[arg(long)]
+    pub bad_checksum_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text, one line per header.
+    #[default]
+    Text,
+    /// One JSON object per frame and line.
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimeFormat {
+    /// Wall clock time in UTC.
+    #[default]
+    Absolute,
+    /// Seconds since the first frame shown.
+    Relative,
+    /// Seconds since the previous frame shown.
+    Delta,
+}
+
+#[derive(Args, Debug, Default)]
+#[command(next_help_heading = "Follow")]
+pub struct Follow {
+    /// Reassemble the TCP streams that pass the filters and show their
+    /// payload instead of the decoded headers. Streams idle for five minutes
+    /// of capture time are forgotten.
+    #[arg(long = "follow")]
+    pub enabled: bool,
+
+    /// Write the payload of each TCP stream direction to its own file in the
+    /// provided directory instead of showing it.
+    #[arg(long = "follow-dir", value_name = "DIR", requires = "enabled")]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Default)]
+#[command(next_help_heading = "Stop")]
+pub struct Stop {
+    /// Stop once the provided number of frames passed the filters.
+    #[arg(short = 'c', long)]
+    pub count: Option<u64>,
+
+    /// Stop after the provided number of seconds.
+    #[arg(long, value_name = "SECS")]
+    pub duration: Option<u64>,
+
+    /// Stop before the frames that passed the filters add up to more than the
+    /// provided number of bytes.
+    #[arg(long, value_name = "BYTES")]
+    pub max_bytes: Option<u64>,
 }
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None, styles = get_styles())]
 pub struct HexRead {
-    /// File containing the hex encoded packets.
+    /// File containing the hex encoded packets, or - for standard input.
     pub file: String,
+
+    /// How to show frames.
+    #[arg(long, value_enum, default_value_t, conflicts_with = "enabled")]
+    pub output: OutputFormat,
+
+    #[command(flatten)]
+    pub filter: Filter,
+    #[command(flatten)]
+    pub follow: Follow,
+    #[command(flatten)]
+    pub stop: Stop,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, styles = get_styles())]
+pub struct PcapRead {
+    /// File containing the pcap or pcapng capture, or - for standard input.
+    pub file: String,
+
+    /// Dump Ethernet frame in hex format.
+    #[arg(long)]
+    pub hex: bool,
+
+    /// How to show frames.
+    #[arg(long, value_enum, default_value_t, conflicts_with = "enabled")]
+    pub output: OutputFormat,
+
+    /// Show capture times in text output, in the provided format.
+    #[arg(long, value_enum)]
+    pub time: Option<TimeFormat>,
+
+    #[command(flatten)]
+    pub filter: Filter,
+    #[command(flatten)]
+    pub follow: Follow,
+    #[command(flatten)]
+    pub stop: Stop,
 }
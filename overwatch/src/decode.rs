@@ -0,0 +1,2206 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Decoding of frames into a [`DecodedPacket`].
+//!
+//! Decoding turns the headers found by the parser, along with the frame they
+//! were found in, into plain data: the header fields, where each header
+//! starts, the payload carried by the transport layer and whether checksums
+//! hold. Nothing is printed here. The text output in [`crate::dump`] and the
+//! JSON output in [`crate::json`] are renderers over the decoded packet, and
+//! the model serializes to the layers of a JSON object as is.
+
+use crate::checksum::{Check, Checksums};
+use crate::dump::{
+    bv_to_ipv4, bv_to_ipv6, bv_to_mac, Afi, ArpHwType, ArpOpcode, BfdAuthType,
+    BfdDiagnostic, BfdStatus, BgpCapability, BgpErrorCode, BgpMessageType,
+    BgpOrigin, BgpPathAttr, DdmRouterKind, Ethertype, GeneveOptClass,
+    Icmp6Type, IcmpType, IpProto, Ipv6OptType, OxideOptType, OxideReplication,
+    Safi, SidecarCode, TcpOptKind,
+};
+use crate::headers_t;
+use bitvec::prelude::*;
+use macaddr::MacAddr6;
+use num_enum::TryFromPrimitive;
+use p4rs::Header;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Display, LowerHex};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// Ports the application protocol is recognized by, as in p4/parse.p4. The
+// parser_and_decoder_agree test checks that both recognize the same ones.
+pub const DDM_EXCHANGE_PORT: u16 = 0x1dde;
+pub const BGP_PORT: u16 = 179;
+pub const HTTP_PORTS: &[u16] = &[80, 8080, 8000, 12221, 12224];
+pub const BFD_ECHO_PORT: u16 = 3785;
+
+/// A protocol constant as found in a header, which may not be one with a
+/// name. Serialized as its name, or as its value in hex.
+pub struct Code<E: TryFromPrimitive>(pub E::Primitive);
+
+impl<E: TryFromPrimitive> Code<E> {
+    /// The constant, if it is a known one.
+    pub fn known(&self) -> Option<E> {
+        E::try_from_primitive(self.0).ok()
+    }
+}
+
+impl<E: TryFromPrimitive> Clone for Code<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E: TryFromPrimitive> Copy for Code<E> {}
+
+impl<E> Display for Code<E>
+where
+    E: TryFromPrimitive + Debug,
+    E::Primitive: LowerHex,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.known() {
+            Some(e) => write!(f, "{:?}", e),
+            None => write!(f, "{:#x}", self.0),
+        }
+    }
+}
+
+impl<E> Debug for Code<E>
+where
+    E: TryFromPrimitive + Debug,
+    E::Primitive: LowerHex,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl<E> Serialize for Code<E>
+where
+    E: TryFromPrimitive + Debug,
+    E::Primitive: LowerHex,
+{
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+/// A checksum along with the value it should have, when that could be
+/// worked out.
+#[derive(Clone, Copy, Debug)]
+pub struct Checksum {
+    pub value: u16,
+    pub expected: Option<u16>,
+}
+
+impl Checksum {
+    fn new(value: u16, check: Option<Check>) -> Self {
+        Self {
+            value,
+            expected: check.map(|c| c.expected),
+        }
+    }
+
+    pub fn is_bad(&self) -> bool {
+        self.expected.is_some_and(|e| e != self.value)
+    }
+}
+
+/// Serialized as `checksum`, and `checksum_ok` when it could be verified.
+impl Serialize for Checksum {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut m = s.serialize_map(None)?;
+        m.serialize_entry("checksum", &self.value)?;
+        if let Some(expected) = self.expected {
+            m.serialize_entry("checksum_ok", &(expected == self.value))?;
+        }
+        m.end()
+    }
+}
+
+/// A frame decoded layer by layer. Each layer is present when the parser
+/// found its header. The `inner_` layers are those encapsulated by Geneve.
+#[derive(Debug, Default, Serialize)]
+pub struct DecodedPacket<'a> {
+    /// The frame the packet was decoded from.
+    #[serde(skip)]
+    pub frame: &'a [u8],
+    #[serde(skip)]
+    pub checksums: Checksums,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eth: Option<Eth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vlan: Option<Vlan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sidecar: Option<Sidecar>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lldp: Option<Lldp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arp: Option<Arp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<Ipv4>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<Ipv6>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l4: Option<L4<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<App>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geneve: Option<Geneve<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_eth: Option<Eth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_arp: Option<Arp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_ipv4: Option<Ipv4>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_ipv6: Option<Ipv6>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_l4: Option<L4<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Eth {
+    /// Offset of the header in the frame.
+    pub offset: usize,
+    #[serde(serialize_with = "display")]
+    pub src: MacAddr6,
+    #[serde(serialize_with = "display")]
+    pub dst: MacAddr6,
+    pub ether_type: Code<Ethertype>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Vlan {
+    pub offset: usize,
+    pub vid: u16,
+    pub pcp: u8,
+    pub dei: bool,
+    pub ether_type: Code<Ethertype>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sidecar {
+    pub offset: usize,
+    pub code: Code<SidecarCode>,
+    pub ingress: u16,
+    pub egress: u16,
+    pub ether_type: Code<Ethertype>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Lldp {
+    Decoded {
+        chassis_id: String,
+        port_id: String,
+        ttl: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        port_description: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        system_name: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        system_description: Option<String>,
+        management_addresses: Vec<String>,
+        organizationally_specific: Vec<String>,
+    },
+    /// The LLDPDU could not be parsed.
+    Malformed { error: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Arp {
+    pub offset: usize,
+    pub hw_type: Code<ArpHwType>,
+    pub proto_type: Code<Ethertype>,
+    pub hw_addr_len: u8,
+    pub proto_addr_len: u8,
+    pub opcode: Code<ArpOpcode>,
+    #[serde(serialize_with = "display")]
+    pub sender_mac: MacAddr6,
+    pub sender_ip: Ipv4Addr,
+    #[serde(serialize_with = "display")]
+    pub target_mac: MacAddr6,
+    pub target_ip: Ipv4Addr,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ipv4 {
+    pub offset: usize,
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub ihl: u8,
+    pub diffserv: u8,
+    pub total_len: u16,
+    pub id: u16,
+    pub flags: u8,
+    pub frag_offset: u16,
+    pub ttl: u8,
+    pub protocol: Code<IpProto>,
+    #[serde(flatten)]
+    pub checksum: Checksum,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ipv6 {
+    pub offset: usize,
+    pub src: Ipv6Addr,
+    pub dst: Ipv6Addr,
+    pub traffic_class: u8,
+    pub flow_label: u32,
+    pub payload_len: u16,
+    pub hop_limit: u8,
+    pub next_hdr: Code<IpProto>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ext: Vec<Ipv6Ext>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Ipv6Ext {
+    HopByHop {
+        next_hdr: Code<IpProto>,
+        len: usize,
+        opts: Vec<Code<Ipv6OptType>>,
+    },
+    DstOpts {
+        next_hdr: Code<IpProto>,
+        len: usize,
+        opts: Vec<Code<Ipv6OptType>>,
+    },
+    Routing {
+        next_hdr: Code<IpProto>,
+        len: usize,
+        routing_type: u8,
+        segments_left: u8,
+    },
+    Fragment {
+        next_hdr: Code<IpProto>,
+        offset: u16,
+        more: bool,
+        id: u32,
+    },
+    Ddm {
+        next_hdr: Code<IpProto>,
+        len: usize,
+        version: u8,
+        ack: bool,
+        elements: Vec<DdmElement>,
+    },
+    /// The chain ends with a header cut short, or one with a bad length.
+    Truncated,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DdmElement {
+    pub id: u8,
+    pub timestamp: u32,
+    /// Time elapsed since the previous element.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "proto", rename_all = "snake_case")]
+pub enum L4<'a> {
+    Tcp(Tcp<'a>),
+    Udp(Udp<'a>),
+    Icmp(Icmp<IcmpType>),
+    Icmp6(Icmp<Icmp6Type>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tcp<'a> {
+    pub offset: usize,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub data_offset: u8,
+    pub reserved: u8,
+    pub flags: TcpFlags,
+    pub window: u16,
+    #[serde(flatten)]
+    pub checksum: Checksum,
+    pub urgent_ptr: u16,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub opts: Vec<TcpOpt>,
+    /// The segment's data, up to the end of the IP packet or of the frame.
+    #[serde(skip)]
+    pub payload: &'a [u8],
+}
+
+/// TCP flags, serialized as the names of those that are set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+    pub const URG: u8 = 0x20;
+    pub const ECE: u8 = 0x40;
+    pub const CWR: u8 = 0x80;
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.0 & flag != 0
+    }
+
+    /// The names of the flags that are set, in the order they are shown.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (Self::CWR, "CWR"),
+            (Self::ECE, "ECE"),
+            (Self::URG, "URG"),
+            (Self::PSH, "PSH"),
+            (Self::RST, "RST"),
+            (Self::SYN, "SYN"),
+            (Self::ACK, "ACK"),
+            (Self::FIN, "FIN"),
+        ]
+        .iter()
+        .filter(|(f, _)| self.has(*f))
+        .map(|(_, name)| *name)
+        .collect()
+    }
+}
+
+impl Serialize for TcpFlags {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.names().serialize(s)
+    }
+}
+
+/// A TCP option. No-operation padding is not kept.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TcpOpt {
+    Mss {
+        mss: u16,
+    },
+    WindowScale {
+        shift: u8,
+    },
+    SackPermitted,
+    Sack {
+        blocks: Vec<(u32, u32)>,
+    },
+    Timestamps {
+        value: u32,
+        echo: u32,
+    },
+    Md5,
+    Ao {
+        key_id: u8,
+        rnext_key_id: u8,
+    },
+    /// A known option with a length that does not fit it.
+    Malformed {
+        option: Code<TcpOptKind>,
+    },
+    Unknown {
+        code: u8,
+        len: usize,
+    },
+    /// An option running past the end of the options, where decoding
+    /// stopped.
+    Truncated,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Udp<'a> {
+    pub offset: usize,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub len: u16,
+    #[serde(flatten)]
+    pub checksum: Checksum,
+    /// The datagram's data, up to the end of the IP packet or of the frame.
+    #[serde(skip)]
+    pub payload: &'a [u8],
+}
+
+/// An ICMP or ICMPv6 header, `T` being the type of message.
+#[derive(Debug, Serialize)]
+#[serde(bound = "T: Debug")]
+pub struct Icmp<T: TryFromPrimitive<Primitive = u8>> {
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub typ: Code<T>,
+    pub code: u8,
+    #[serde(flatten)]
+    pub checksum: Checksum,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub echo: Option<Echo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Echo {
+    pub id: u16,
+    pub seq: u16,
+}
+
+/// The application protocol carried by the transport layer.
+#[derive(Debug, Serialize)]
+#[serde(tag = "proto", rename_all = "snake_case")]
+pub enum App {
+    Bfd(Bfd),
+    BfdEcho { len: usize },
+    DdmDiscovery(DdmDiscovery),
+    DdmExchange(Http),
+    Bgp(Bgp),
+    Http(Http),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Bfd {
+    pub version: u8,
+    pub status: Code<BfdStatus>,
+    pub diag: Code<BfdDiagnostic>,
+    pub flags: Vec<&'static str>,
+    pub detect_mult: u8,
+    pub len: u8,
+    pub my_discriminator: u32,
+    pub your_discriminator: u32,
+    pub desired_min_tx_interval: u32,
+    pub required_min_tx_interval: u32,
+    pub required_min_echo_rx_interval: u32,
+    /// The authentication section, when the flag for it is set and it is
+    /// all there.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<BfdAuth>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BfdAuth {
+    #[serde(rename = "type")]
+    pub typ: Code<BfdAuthType>,
+    pub len: u8,
+    pub key: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// The sequence number of the keyed types, when it is all there.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DdmDiscovery {
+    pub version: u8,
+    pub flags: Vec<&'static str>,
+    pub kind: Code<DdmRouterKind>,
+    #[serde(skip)]
+    pub hostname_len: u8,
+    pub hostname: String,
+}
+
+/// The BGP messages in a TCP segment.
+#[derive(Debug, Default, Serialize)]
+pub struct Bgp {
+    /// Length of the data continuing a message from an earlier segment, when
+    /// the segment does not start with a message.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub continued: usize,
+    pub messages: Vec<BgpMessage>,
+    /// Length of the data after the last message that does not start
+    /// another one.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub trailing: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BgpMessage {
+    #[serde(rename = "type")]
+    pub typ: Code<BgpMessageType>,
+    pub len: usize,
+    /// How much of the message is in the segment, when the rest is in the
+    /// next one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captured: Option<usize>,
+    /// The decoded message, for those that have more than a header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<BgpBody>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BgpBody {
+    Open(BgpOpen),
+    Update(BgpUpdate),
+    Notification(BgpNotification),
+    Malformed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BgpOpen {
+    pub version: u8,
+    /// The AS number, the four octet one when that capability is present.
+    pub asn: u32,
+    pub hold: u16,
+    pub id: Ipv4Addr,
+    pub caps: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BgpUpdate {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub withdrawn: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attrs: Vec<BgpAttr>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nlri: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BgpAttr {
+    #[serde(rename = "type")]
+    pub typ: Code<BgpPathAttr>,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BgpNotification {
+    pub code: Code<BgpErrorCode>,
+    pub subcode: u8,
+    pub data_len: usize,
+}
+
+/// An HTTP segment. The start line and headers are only there when the
+/// segment starts a message.
+#[derive(Debug, Default, Serialize)]
+pub struct Http {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Header names and values in the order they appear. Serialized as a map
+    /// keyed on the lowercase name.
+    #[serde(
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "header_map"
+    )]
+    pub headers: Vec<(String, String)>,
+    pub len: usize,
+}
+
+impl Http {
+    /// Whether the segment starts a message.
+    pub fn is_start(&self) -> bool {
+        self.method.is_some() || self.status.is_some()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Geneve<'a> {
+    pub offset: usize,
+    pub vni: u32,
+    pub version: u8,
+    pub opt_len: u8,
+    pub ctrl: bool,
+    pub crit: bool,
+    pub protocol: Code<Ethertype>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub opts: Vec<GeneveOpt<'a>>,
+    /// Whether the options end with one cut short.
+    #[serde(skip_serializing_if = "is_false")]
+    pub opts_truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GeneveOpt<'a> {
+    pub class: Code<GeneveOptClass>,
+    #[serde(rename = "type")]
+    pub typ: GeneveOptType,
+    pub crit: bool,
+    /// The option data, serialized in hex.
+    #[serde(serialize_with = "hex")]
+    pub data: &'a [u8],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication: Option<Code<OxideReplication>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mss: Option<u32>,
+}
+
+/// The type of a Geneve option, which is only known within a known class.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(untagged)]
+pub enum GeneveOptType {
+    Oxide(Code<OxideOptType>),
+    Other(#[serde(serialize_with = "lower_hex")] u8),
+}
+
+fn display<T: Display, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(v)
+}
+
+fn lower_hex<T: LowerHex, S: Serializer>(
+    v: &T,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.collect_str(&format_args!("{:#x}", v))
+}
+
+fn hex<S: Serializer>(data: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+    s.serialize_str(&hex)
+}
+
+fn header_map<S: Serializer>(
+    headers: &[(String, String)],
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    let map: BTreeMap<String, &str> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.as_str()))
+        .collect();
+    map.serialize(s)
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn bit(bv: &BitSlice<u8, Msb0>, i: usize) -> bool {
+    bv.get(i).as_deref() == Some(&true)
+}
+
+macro_rules! hlen {
+    ($hdr:tt) => {
+        crate::$hdr::size() >> 3
+    };
+}
+
+impl<'a> DecodedPacket<'a> {
+    /// Decode the headers the parser found in `frame`.
+    #[allow(unused_assignments)]
+    pub fn decode(h: &headers_t, frame: &'a [u8]) -> Self {
+        let sums = Checksums::new(h, frame);
+        let mut p = Self {
+            frame,
+            checksums: sums,
+            ..Default::default()
+        };
+        let mut off = 0usize;
+        // End of the IP packet, anything beyond is link layer padding.
+        let mut end = frame.len();
+        if h.ethernet.isValid() {
+            p.eth = eth(&h.ethernet, off);
+            off += hlen!(ethernet_h);
+        }
+        if h.vlan.isValid() {
+            p.vlan = Some(vlan(&h.vlan, off));
+            off += hlen!(vlan_h);
+        }
+        if h.sidecar.isValid() {
+            p.sidecar = Some(sidecar(&h.sidecar, off));
+            off += hlen!(sidecar_h);
+        }
+        if h.lldp.isValid() {
+            p.lldp = Some(lldp(frame.get(off..).unwrap_or_default()));
+        }
+        if h.arp.isValid() {
+            p.arp = arp(&h.arp, off);
+            off += hlen!(arp_h);
+        }
+        if h.ipv4.isValid() {
+            let ihl: u8 = h.ipv4.ihl.load();
+            let len: u16 = h.ipv4.total_len.load_le();
+            end = end.min(off + len as usize);
+            p.ipv4 = ipv4(&h.ipv4, off, sums.ipv4);
+            off += (ihl << 2) as usize;
+        } else if h.ipv6.isValid() {
+            let len: u16 = h.ipv6.payload_len.load_le();
+            end = end.min(off + hlen!(ipv6_h) + len as usize);
+            let (ip, len) = ipv6(&h.ipv6, frame, off);
+            p.ipv6 = ip;
+            off += len;
+        }
+        if h.icmp.isValid() {
+            let echo = h.echo.isValid().then(|| echo(&h.echo));
+            p.l4 = Some(icmp(&h.icmp, h.ipv6.isValid(), off, sums.icmp, echo));
+        }
+        if h.tcp.isValid() {
+            let tcp = tcp(&h.tcp, frame, off, end, sums.tcp);
+            off += tcp.payload_offset();
+            p.app = tcp_app(&tcp);
+            p.l4 = Some(L4::Tcp(tcp));
+        }
+        if h.udp.isValid() {
+            let udp = udp(&h.udp, frame, off, end, sums.udp);
+            off += hlen!(udp_h);
+            if udp.dst_port == BFD_ECHO_PORT {
+                p.app = Some(App::BfdEcho {
+                    len: udp.payload.len(),
+                });
+            }
+            p.l4 = Some(L4::Udp(udp));
+        }
+        if h.ddm_discovery.isValid() {
+            let host = frame
+                .get(off + hlen!(ddm_discovery_h)..)
+                .unwrap_or_default();
+            p.app =
+                Some(App::DdmDiscovery(ddm_discovery(&h.ddm_discovery, host)));
+            off += hlen!(ddm_discovery_h);
+        }
+        if h.bfd.isValid() {
+            off += hlen!(bfd_h);
+            let auth = bit(&h.bfd.authentication_present, 0)
+                .then(|| bfd_auth(frame.get(off..end).unwrap_or_default()))
+                .flatten();
+            p.app = Some(App::Bfd(bfd(&h.bfd, auth)));
+        }
+        if h.geneve.isValid() {
+            let olen: u8 = h.geneve.opt_len.load();
+            let start = off + hlen!(geneve_h);
+            let end = (start + ((olen as usize) << 2)).min(frame.len());
+            let opts = frame.get(start..end).unwrap_or_default();
+            p.geneve = Some(geneve(&h.geneve, off, opts));
+            off = end;
+        }
+
+        // The encapsulated frame has its own IP packet length.
+        let mut end = frame.len();
+        if h.inner_eth.isValid() {
+            p.inner_eth = eth(&h.inner_eth, off);
+            off += hlen!(ethernet_h);
+        }
+        if h.inner_arp.isValid() {
+            p.inner_arp = arp(&h.inner_arp, off);
+            off += hlen!(arp_h);
+        }
+        if h.inner_ipv4.isValid() {
+            let ihl: u8 = h.inner_ipv4.ihl.load();
+            let len: u16 = h.inner_ipv4.total_len.load_le();
+            end = end.min(off + len as usize);
+            p.inner_ipv4 = ipv4(&h.inner_ipv4, off, sums.inner_ipv4);
+            off += (ihl << 2) as usize;
+        } else if h.inner_ipv6.isValid() {
+            let len: u16 = h.inner_ipv6.payload_len.load_le();
+            end = end.min(off + hlen!(ipv6_h) + len as usize);
+            let (ip, len) = ipv6(&h.inner_ipv6, frame, off);
+            p.inner_ipv6 = ip;
+            off += len;
+        }
+        if h.inner_icmp.isValid() {
+            let echo = h.inner_echo.isValid().then(|| echo(&h.inner_echo));
+            let v6 = h.inner_ipv6.isValid();
+            p.inner_l4 =
+                Some(icmp(&h.inner_icmp, v6, off, sums.inner_icmp, echo));
+        }
+        if h.inner_tcp.isValid() {
+            let tcp = tcp(&h.inner_tcp, frame, off, end, sums.inner_tcp);
+            p.inner_l4 = Some(L4::Tcp(tcp));
+        }
+        if h.inner_udp.isValid() {
+            let udp = udp(&h.inner_udp, frame, off, end, sums.inner_udp);
+            p.inner_l4 = Some(L4::Udp(udp));
+        }
+        p
+    }
+}
+
+fn eth(h: &crate::ethernet_h, offset: usize) -> Option<Eth> {
+    Some(Eth {
+        offset,
+        src: bv_to_mac(h.src.clone()).ok()?,
+        dst: bv_to_mac(h.dst.clone()).ok()?,
+        ether_type: Code(h.ether_type.load_le()),
+    })
+}
+
+fn vlan(h: &crate::vlan_h, offset: usize) -> Vlan {
+    Vlan {
+        offset,
+        vid: h.vid.load_le(),
+        pcp: h.pcp.load(),
+        dei: bit(&h.dei, 0),
+        ether_type: Code(h.ether_type.load_le()),
+    }
+}
+
+fn sidecar(h: &crate::sidecar_h, offset: usize) -> Sidecar {
+    Sidecar {
+        offset,
+        code: Code(h.sc_code.load()),
+        ingress: h.sc_ingress.load_le(),
+        egress: h.sc_egress.load_le(),
+        ether_type: Code(h.sc_ether_type.load_le()),
+    }
+}
+
+fn lldp(data: &[u8]) -> Lldp {
+    let l = match lldp::types::Lldpdu::try_from(data) {
+        Ok(l) => l,
+        Err(e) => {
+            return Lldp::Malformed {
+                error: e.to_string(),
+            }
+        }
+    };
+    Lldp::Decoded {
+        chassis_id: l.chassis_id.to_string(),
+        port_id: l.port_id.to_string(),
+        ttl: l.ttl.to_string(),
+        port_description: l.port_description.clone(),
+        system_name: l.system_name.clone(),
+        system_description: l.system_description.clone(),
+        management_addresses: l
+            .management_addresses
+            .iter()
+            .map(|ma| format!("{ma:?}"))
+            .collect(),
+        organizationally_specific: l
+            .organizationally_specific
+            .iter()
+            .map(|os| os.to_string())
+            .collect(),
+    }
+}
+
+fn arp(h: &crate::arp_h, offset: usize) -> Option<Arp> {
+    Some(Arp {
+        offset,
+        hw_type: Code(h.hw_type.load_le()),
+        proto_type: Code(h.proto_type.load_le()),
+        hw_addr_len: h.hw_addr_len.load(),
+        proto_addr_len: h.proto_addr_len.load(),
+        opcode: Code(h.opcode.load_le()),
+        sender_mac: bv_to_mac(h.sender_mac.clone()).ok()?,
+        sender_ip: bv_to_ipv4(h.sender_ip.clone()).ok()?,
+        target_mac: bv_to_mac(h.target_mac.clone()).ok()?,
+        target_ip: bv_to_ipv4(h.target_ip.clone()).ok()?,
+    })
+}
+
+fn ipv4(
+    h: &crate::ipv4_h,
+    offset: usize,
+    check: Option<Check>,
+) -> Option<Ipv4> {
+    Some(Ipv4 {
+        offset,
+        src: bv_to_ipv4(h.src.clone()).ok()?,
+        dst: bv_to_ipv4(h.dst.clone()).ok()?,
+        ihl: h.ihl.load(),
+        diffserv: h.diffserv.load(),
+        total_len: h.total_len.load_le(),
+        id: h.identification.load_le(),
+        flags: h.flags.load(),
+        frag_offset: h.frag_offset.load_le(),
+        ttl: h.ttl.load(),
+        protocol: Code(h.protocol.load()),
+        checksum: Checksum::new(h.hdr_checksum.load_le(), check),
+    })
+}
+
+/// Decode an IPv6 header along with its extension headers, returning it and
+/// the length of the headers.
+fn ipv6(
+    h: &crate::ipv6_h,
+    frame: &[u8],
+    offset: usize,
+) -> (Option<Ipv6>, usize) {
+    let start = offset + hlen!(ipv6_h);
+    let (ext, len) =
+        ipv6_exts(h.next_hdr.load(), frame.get(start..).unwrap_or_default());
+    let ip = || {
+        Some(Ipv6 {
+            offset,
+            src: bv_to_ipv6(h.src.clone()).ok()?,
+            dst: bv_to_ipv6(h.dst.clone()).ok()?,
+            traffic_class: h.traffic_class.load(),
+            flow_label: h.flow_label.load_le(),
+            payload_len: h.payload_len.load_le(),
+            hop_limit: h.hop_limit.load(),
+            next_hdr: Code(h.next_hdr.load()),
+            ext,
+        })
+    };
+    (ip(), hlen!(ipv6_h) + len)
+}
+
+/// Walk the extension headers following an IPv6 header whose next header is
+/// `next`, returning the length of the chain, the upper layer protocol and
+/// whether the packet is not fragmented. `None` if the chain is truncated.
+pub(crate) fn ipv6_upper_layer(
+    next: u8,
+    frame: &[u8],
+) -> Option<(usize, u8, bool)> {
+    let (exts, len) = ipv6_exts(next, frame);
+    let mut proto = next;
+    let mut whole = true;
+    for ext in &exts {
+        proto = match ext {
+            Ipv6Ext::HopByHop { next_hdr, .. }
+            | Ipv6Ext::DstOpts { next_hdr, .. }
+            | Ipv6Ext::Routing { next_hdr, .. }
+            | Ipv6Ext::Ddm { next_hdr, .. } => next_hdr.0,
+            Ipv6Ext::Fragment {
+                next_hdr,
+                offset,
+                more,
+                ..
+            } => {
+                whole &= *offset == 0 && !more;
+                next_hdr.0
+            }
+            Ipv6Ext::Truncated => return None,
+        };
+    }
+    Some((len, proto, whole))
+}
+
+/// Decode the chain of extension headers following an IPv6 header whose next
+/// header is `next`, returning the headers and the length of the chain.
+fn ipv6_exts(mut next: u8, frame: &[u8]) -> (Vec<Ipv6Ext>, usize) {
+    let mut exts = Vec::new();
+    let mut off = 0;
+    loop {
+        let data = &frame[off..];
+        let ext = match IpProto::try_from(next) {
+            Ok(IpProto::Ipv6Hbh) => {
+                ipv6_opts(data).map(|(len, next_hdr, opts)| {
+                    (
+                        len,
+                        Ipv6Ext::HopByHop {
+                            next_hdr,
+                            len,
+                            opts,
+                        },
+                    )
+                })
+            }
+            Ok(IpProto::Ipv6DstOpt) => {
+                ipv6_opts(data).map(|(len, next_hdr, opts)| {
+                    (
+                        len,
+                        Ipv6Ext::DstOpts {
+                            next_hdr,
+                            len,
+                            opts,
+                        },
+                    )
+                })
+            }
+            Ok(IpProto::Ipv6Rth) => match data {
+                [next, len, typ, left, ..] => {
+                    let len = (*len as usize + 1) << 3;
+                    let ext = Ipv6Ext::Routing {
+                        next_hdr: Code(*next),
+                        len,
+                        routing_type: *typ,
+                        segments_left: *left,
+                    };
+                    Some((len, ext))
+                }
+                _ => None,
+            },
+            Ok(IpProto::Ipv6Frag) => match data {
+                [next, _, a, b, c, d, e, f, ..] => {
+                    let ext = Ipv6Ext::Fragment {
+                        next_hdr: Code(*next),
+                        offset: u16::from_be_bytes([*a, *b]) & !0x7,
+                        more: b & 0x1 != 0,
+                        id: u32::from_be_bytes([*c, *d, *e, *f]),
+                    };
+                    Some((8, ext))
+                }
+                _ => None,
+            },
+            Ok(IpProto::Ddm) => ddm(data),
+            _ => return (exts, off),
+        };
+        let Some((len, ext)) = ext.filter(|(len, _)| *len <= data.len()) else {
+            exts.push(Ipv6Ext::Truncated);
+            return (exts, off);
+        };
+        // Only the first fragment carries the upper layer header.
+        let done =
+            matches!(ext, Ipv6Ext::Fragment { offset, .. } if offset != 0);
+        exts.push(ext);
+        off += len;
+        if done {
+            return (exts, off);
+        }
+        next = data[0];
+    }
+}
+
+/// Decode a hop-by-hop or destination options header, returning its length,
+/// next header and options. Padding is left out.
+fn ipv6_opts(
+    data: &[u8],
+) -> Option<(usize, Code<IpProto>, Vec<Code<Ipv6OptType>>)> {
+    let len = (*data.get(1)? as usize + 1) << 3;
+    let mut opts = Vec::new();
+    let mut rest = data.get(2..len)?;
+    while let [typ, tail @ ..] = rest {
+        if *typ == Ipv6OptType::Pad1 as u8 {
+            rest = tail;
+            continue;
+        }
+        let [olen, tail @ ..] = tail else { break };
+        rest = tail.get(*olen as usize..).unwrap_or_default();
+        if *typ != Ipv6OptType::PadN as u8 {
+            opts.push(Code(*typ));
+        }
+    }
+    Some((len, Code(data[0]), opts))
+}
+
+/// Decode a DDM extension header. The length is in octets and includes the
+/// 4 octet fixed part, which is followed by 4 octet timestamp elements. Each
+/// element is stamped by a router on the path.
+fn ddm(data: &[u8]) -> Option<(usize, Ipv6Ext)> {
+    let len = *data.get(1)? as usize;
+    if len < 4 {
+        return None;
+    }
+    let mut elements = Vec::new();
+    let mut prev: Option<u32> = None;
+    for e in data.get(4..len)?.chunks(4) {
+        let [id, a, b, c] = *e else { return None };
+        let ts = u32::from_be_bytes([0, a, b, c]);
+        elements.push(DdmElement {
+            id,
+            timestamp: ts,
+            // Timestamps are 24 bits wide and wrap around.
+            delta: prev.map(|p| ts.wrapping_sub(p) & 0xff_ffff),
+        });
+        prev = Some(ts);
+    }
+    let ext = Ipv6Ext::Ddm {
+        next_hdr: Code(data[0]),
+        len,
+        version: data[2],
+        ack: data[3] & 0x80 != 0,
+        elements,
+    };
+    Some((len, ext))
+}
+
+fn icmp<'a>(
+    h: &crate::icmp_h,
+    v6: bool,
+    offset: usize,
+    check: Option<Check>,
+    echo: Option<Echo>,
+) -> L4<'a> {
+    let typ: u8 = h.typ.load_le();
+    let code: u8 = h.code.load_le();
+    let checksum = Checksum::new(h.hdr_checksum.load_le(), check);
+    if v6 {
+        L4::Icmp6(Icmp {
+            offset,
+            typ: Code(typ),
+            code,
+            checksum,
+            echo,
+        })
+    } else {
+        L4::Icmp(Icmp {
+            offset,
+            typ: Code(typ),
+            code,
+            checksum,
+            echo,
+        })
+    }
+}
+
+fn echo(h: &crate::echo_h) -> Echo {
+    Echo {
+        id: h.id.load_le(),
+        seq: h.seq.load_le(),
+    }
+}
+
+impl Tcp<'_> {
+    /// Offset of the payload from the start of the header.
+    pub fn payload_offset(&self) -> usize {
+        (self.data_offset as usize) << 2
+    }
+}
+
+fn tcp<'a>(
+    h: &crate::tcp_h,
+    frame: &'a [u8],
+    offset: usize,
+    end: usize,
+    check: Option<Check>,
+) -> Tcp<'a> {
+    let doff: u8 = h.data_offset.load();
+    let start = offset + ((doff as usize) << 2);
+    let opts = frame.get(offset + hlen!(tcp_h)..start).unwrap_or_default();
+    Tcp {
+        offset,
+        src_port: h.src_port.load_le(),
+        dst_port: h.dst_port.load_le(),
+        seq: h.seq_no.load_le(),
+        ack: h.ack_no.load_le(),
+        data_offset: doff,
+        reserved: h.res.load(),
+        flags: TcpFlags(h.flags.load()),
+        window: h.window.load_le(),
+        checksum: Checksum::new(h.checksum.load_le(), check),
+        urgent_ptr: h.urgent_ptr.load_le(),
+        opts: tcp_opts(opts),
+        payload: frame.get(start..end).unwrap_or_default(),
+    }
+}
+
+/// Decode TCP options. No-operation padding is left out and decoding stops
+/// at an option that runs past the end.
+fn tcp_opts(mut data: &[u8]) -> Vec<TcpOpt> {
+    let mut opts = Vec::new();
+    while let [kind, rest @ ..] = data {
+        match TcpOptKind::try_from(*kind) {
+            Ok(TcpOptKind::Eol) => break,
+            Ok(TcpOptKind::Nop) => {
+                data = rest;
+                continue;
+            }
+            _ => {}
+        }
+        let value = match rest {
+            [len, rest @ ..] if *len >= 2 => rest.get(..*len as usize - 2),
+            _ => None,
+        };
+        let Some(value) = value else {
+            opts.push(TcpOpt::Truncated);
+            break;
+        };
+        data = &rest[1 + value.len()..];
+        let be16 = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+        let be32 = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+        opts.push(match (TcpOptKind::try_from(*kind), value) {
+            (Ok(TcpOptKind::Mss), [_, _]) => TcpOpt::Mss { mss: be16(value) },
+            (Ok(TcpOptKind::WindowScale), [shift]) => {
+                TcpOpt::WindowScale { shift: *shift }
+            }
+            (Ok(TcpOptKind::SackPermitted), []) => TcpOpt::SackPermitted,
+            (Ok(TcpOptKind::Sack), _) if value.len() % 8 == 0 => TcpOpt::Sack {
+                blocks: value
+                    .chunks_exact(8)
+                    .map(|b| (be32(&b[..4]), be32(&b[4..])))
+                    .collect(),
+            },
+            (Ok(TcpOptKind::Timestamps), [_, _, _, _, _, _, _, _]) => {
+                TcpOpt::Timestamps {
+                    value: be32(&value[..4]),
+                    echo: be32(&value[4..]),
+                }
+            }
+            (Ok(TcpOptKind::Md5), _) if value.len() == 16 => TcpOpt::Md5,
+            (Ok(TcpOptKind::Ao), [key, rnext, ..]) => TcpOpt::Ao {
+                key_id: *key,
+                rnext_key_id: *rnext,
+            },
+            (Ok(_), _) => TcpOpt::Malformed {
+                option: Code(*kind),
+            },
+            (Err(_), _) => TcpOpt::Unknown {
+                code: *kind,
+                len: value.len() + 2,
+            },
+        });
+    }
+    opts
+}
+
+/// Work out the application protocol of a TCP segment from its ports, or
+/// from its content for HTTP on other ports.
+fn tcp_app(tcp: &Tcp) -> Option<App> {
+    let ports = [tcp.src_port, tcp.dst_port];
+    let payload = tcp.payload;
+    if payload.is_empty() {
+        return None;
+    }
+    if ports.contains(&BGP_PORT) {
+        return Some(App::Bgp(bgp(payload)));
+    }
+    if ports.contains(&DDM_EXCHANGE_PORT) {
+        return Some(App::DdmExchange(http(payload)));
+    }
+    let http = http(payload);
+    if HTTP_PORTS.iter().any(|p| ports.contains(p)) || http.is_start() {
+        return Some(App::Http(http));
+    }
+    None
+}
+
+fn udp<'a>(
+    h: &crate::udp_h,
+    frame: &'a [u8],
+    offset: usize,
+    end: usize,
+    check: Option<Check>,
+) -> Udp<'a> {
+    let start = offset + hlen!(udp_h);
+    Udp {
+        offset,
+        src_port: h.src_port.load_le(),
+        dst_port: h.dst_port.load_le(),
+        len: h.len.load_le(),
+        checksum: Checksum::new(h.checksum.load_le(), check),
+        payload: frame.get(start..end).unwrap_or_default(),
+    }
+}
+
+fn ddm_discovery(h: &crate::ddm_discovery_h, host: &[u8]) -> DdmDiscovery {
+    let len: u8 = h.hostname_len.load();
+    let mut flags = Vec::new();
+    if bit(&h.flags, 7) {
+        flags.push("Solicit");
+    }
+    if bit(&h.flags, 6) {
+        flags.push("Advertise");
+    }
+    let host = host.get(..len as usize).unwrap_or(host);
+    DdmDiscovery {
+        version: h.version.load(),
+        flags,
+        kind: Code(h.router_kind.load()),
+        hostname_len: len,
+        hostname: String::from_utf8_lossy(host).into_owned(),
+    }
+}
+
+fn bfd(h: &crate::bfd_h, auth: Option<BfdAuth>) -> Bfd {
+    let mut flags = Vec::new();
+    for (bv, flag) in [
+        (&h.poll, "poll"),
+        (&h.fin, "final"),
+        (&h.control_plane_independent, "cpi"),
+        (&h.authentication_present, "auth"),
+        (&h.demand, "demand"),
+        (&h.multipoint, "mp"),
+    ] {
+        if bit(bv, 0) {
+            flags.push(flag);
+        }
+    }
+    Bfd {
+        version: h.version.load(),
+        status: Code(h.status.load()),
+        diag: Code(h.diag.load()),
+        flags,
+        detect_mult: h.detect_mult.load(),
+        len: h.len.load(),
+        my_discriminator: h.my_discriminator.load_le(),
+        your_discriminator: h.your_discriminator.load_le(),
+        desired_min_tx_interval: h.desired_min_tx_interval.load_le(),
+        required_min_tx_interval: h.required_min_tx_interval.load_le(),
+        required_min_echo_rx_interval: h
+            .required_min_echo_rx_interval
+            .load_le(),
+        auth,
+    }
+}
+
+/// Decode the authentication section that follows the BFD control header when
+/// the authentication present flag is set.
+fn bfd_auth(data: &[u8]) -> Option<BfdAuth> {
+    let [typ, len, key, rest @ ..] = data else {
+        return None;
+    };
+    let rest = rest.get(..(*len as usize).saturating_sub(3))?;
+    let typ = Code::<BfdAuthType>(*typ);
+    let (password, seq) = match (typ.known(), rest) {
+        (Some(BfdAuthType::SimplePassword), password) => {
+            (Some(String::from_utf8_lossy(password).into_owned()), None)
+        }
+        // The sequence number follows a reserved octet, then the digest.
+        (Some(_), [_, a, b, c, d, ..]) => {
+            (None, Some(u32::from_be_bytes([*a, *b, *c, *d])))
+        }
+        _ => (None, None),
+    };
+    Some(BfdAuth {
+        typ,
+        len: *len,
+        key: *key,
+        password,
+        seq,
+    })
+}
+
+pub const BGP_HEADER_LEN: usize = 19;
+
+/// Decode the BGP messages in a TCP segment. A segment may carry several
+/// messages, and the last one may continue in the next segment.
+fn bgp(mut data: &[u8]) -> Bgp {
+    let mut bgp = Bgp::default();
+    if data.len() < BGP_HEADER_LEN || data[..16] != [0xff; 16] {
+        bgp.continued = data.len();
+        return bgp;
+    }
+    while data.len() >= BGP_HEADER_LEN && data[..16] == [0xff; 16] {
+        let len = u16::from_be_bytes([data[16], data[17]]) as usize;
+        let mut msg = BgpMessage {
+            typ: Code(data[18]),
+            len,
+            captured: None,
+            body: None,
+        };
+        // Nothing after a message of unknown type or length can be trusted
+        // to start another one.
+        let Some(typ) = msg.typ.known().filter(|_| len >= BGP_HEADER_LEN)
+        else {
+            bgp.messages.push(msg);
+            return bgp;
+        };
+        let Some(body) = data.get(BGP_HEADER_LEN..len) else {
+            // The rest of the message is in the next segment.
+            msg.captured = Some(data.len());
+            bgp.messages.push(msg);
+            return bgp;
+        };
+        msg.body = match typ {
+            BgpMessageType::Open => {
+                Some(bgp_open(body).map_or(BgpBody::Malformed, BgpBody::Open))
+            }
+            BgpMessageType::Update => Some(
+                bgp_update(body).map_or(BgpBody::Malformed, BgpBody::Update),
+            ),
+            BgpMessageType::Notification => Some(
+                bgp_notification(body)
+                    .map_or(BgpBody::Malformed, BgpBody::Notification),
+            ),
+            BgpMessageType::Keepalive | BgpMessageType::RouteRefresh => None,
+        };
+        bgp.messages.push(msg);
+        data = &data[len..];
+    }
+    bgp.trailing = data.len();
+    bgp
+}
+
+fn bgp_open(data: &[u8]) -> Option<BgpOpen> {
+    let version = *data.first()?;
+    let mut asn = u32::from(u16::from_be_bytes([*data.get(1)?, *data.get(2)?]));
+    let hold = u16::from_be_bytes([*data.get(3)?, *data.get(4)?]);
+    let id = Ipv4Addr::from(<[u8; 4]>::try_from(data.get(5..9)?).ok()?);
+    let plen = *data.get(9)? as usize;
+    let mut params = data.get(10..10 + plen)?;
+
+    let mut caps = Vec::new();
+    while let [typ, len, rest @ ..] = params {
+        let value = rest.get(..*len as usize)?;
+        params = &rest[*len as usize..];
+        // Capabilities are the only optional parameter in use.
+        if *typ != 2 {
+            caps.push(format!("param{}", typ));
+            continue;
+        }
+        let mut value = value;
+        while let [code, len, rest @ ..] = value {
+            let cap = rest.get(..*len as usize)?;
+            value = &rest[*len as usize..];
+            caps.push(match (BgpCapability::try_from(*code), cap) {
+                (Ok(BgpCapability::MultiProtocol), [a, b, _, safi]) => {
+                    format!(
+                        "MultiProtocol({}/{})",
+                        afi(u16::from_be_bytes([*a, *b])),
+                        safi_name(*safi)
+                    )
+                }
+                (Ok(BgpCapability::FourOctetAs), [a, b, c, d]) => {
+                    asn = u32::from_be_bytes([*a, *b, *c, *d]);
+                    "FourOctetAs".to_string()
+                }
+                (Ok(c), _) => format!("{:?}", c),
+                (Err(_), _) => format!("{}", code),
+            });
+        }
+    }
+    Some(BgpOpen {
+        version,
+        asn,
+        hold,
+        id,
+        caps,
+    })
+}
+
+fn bgp_update(data: &[u8]) -> Option<BgpUpdate> {
+    let wlen = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let withdrawn = data.get(2..2 + wlen)?;
+    let data = &data[2 + wlen..];
+    let alen = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let mut attrs = data.get(2..2 + alen)?;
+    let nlri = &data[2 + alen..];
+
+    let mut update = BgpUpdate {
+        withdrawn: bgp_prefixes(withdrawn, false)?,
+        attrs: Vec::new(),
+        nlri: bgp_prefixes(nlri, false)?,
+    };
+    while let [flags, typ, rest @ ..] = attrs {
+        // Extended length attributes have a two octet length.
+        let (len, rest) = if flags & 0x10 != 0 {
+            let [a, b, rest @ ..] = rest else { return None };
+            (u16::from_be_bytes([*a, *b]) as usize, rest)
+        } else {
+            let [a, rest @ ..] = rest else { return None };
+            (*a as usize, rest)
+        };
+        let value = rest.get(..len)?;
+        attrs = &rest[len..];
+        let value = match BgpPathAttr::try_from(*typ) {
+            Ok(a) => bgp_attr(a, value)?,
+            _ => format!("{} octets", len),
+        };
+        update.attrs.push(BgpAttr {
+            typ: Code(*typ),
+            value,
+        });
+    }
+    Some(update)
+}
+
+fn bgp_attr(attr: BgpPathAttr, data: &[u8]) -> Option<String> {
+    let value = match attr {
+        BgpPathAttr::Origin => match BgpOrigin::try_from(*data.first()?) {
+            Ok(o) => format!("{:?}", o),
+            _ => format!("{}", data[0]),
+        },
+        BgpPathAttr::AsPath => bgp_as_path(data)?,
+        BgpPathAttr::NextHop => {
+            Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?).to_string()
+        }
+        BgpPathAttr::Med | BgpPathAttr::LocalPref => {
+            u32::from_be_bytes(data.try_into().ok()?).to_string()
+        }
+        BgpPathAttr::Communities => data
+            .chunks_exact(4)
+            .map(|c| {
+                let hi = u16::from_be_bytes([c[0], c[1]]);
+                let lo = u16::from_be_bytes([c[2], c[3]]);
+                format!("{}:{}", hi, lo)
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        BgpPathAttr::MpReachNlri => {
+            let [a, b, safi, nhlen, rest @ ..] = data else {
+                return None;
+            };
+            let afi_num = u16::from_be_bytes([*a, *b]);
+            let nh = rest.get(..*nhlen as usize)?;
+            // Skip the reserved octet after the next hop.
+            let nlri = rest.get(*nhlen as usize + 1..)?;
+            let v6 = afi_num == Afi::Ipv6 as u16;
+            let nh = match (v6, nh.len()) {
+                // A link local address may follow the global one.
+                (true, 16 | 32) => {
+                    Ipv6Addr::from(<[u8; 16]>::try_from(&nh[..16]).ok()?)
+                        .to_string()
+                }
+                (false, 4) => {
+                    Ipv4Addr::from(<[u8; 4]>::try_from(nh).ok()?).to_string()
+                }
+                _ => format!("{} octets", nh.len()),
+            };
+            format!(
+                "{}/{} nh {} nlri {}",
+                afi(afi_num),
+                safi_name(*safi),
+                nh,
+                bgp_prefixes(nlri, v6)?.join(" ")
+            )
+        }
+        BgpPathAttr::MpUnreachNlri => {
+            let [a, b, safi, rest @ ..] = data else {
+                return None;
+            };
+            let afi_num = u16::from_be_bytes([*a, *b]);
+            let v6 = afi_num == Afi::Ipv6 as u16;
+            format!(
+                "{}/{} withdrawn {}",
+                afi(afi_num),
+                safi_name(*safi),
+                bgp_prefixes(rest, v6)?.join(" ")
+            )
+        }
+        _ => format!("{} octets", data.len()),
+    };
+    Some(value)
+}
+
+/// Decode an AS path. Peers that negotiated four octet AS numbers send four
+/// octet path segments, which is assumed when the segment lengths add up.
+fn bgp_as_path(data: &[u8]) -> Option<String> {
+    let fits = |width: usize| {
+        let mut rest = data;
+        while let [_, count, tail @ ..] = rest {
+            match tail.get(*count as usize * width..) {
+                Some(tail) => rest = tail,
+                None => return false,
+            }
+        }
+        rest.is_empty()
+    };
+    let width = if fits(4) { 4 } else { 2 };
+    if !fits(width) {
+        return None;
+    }
+
+    let mut segments = Vec::new();
+    let mut rest = data;
+    while let [typ, count, tail @ ..] = rest {
+        let (asns, tail) = tail.split_at(*count as usize * width);
+        rest = tail;
+        let asns: Vec<String> = asns
+            .chunks_exact(width)
+            .map(|c| {
+                c.iter()
+                    .fold(0u32, |acc, b| acc << 8 | u32::from(*b))
+                    .to_string()
+            })
+            .collect();
+        // AS_SET segments are shown in braces.
+        segments.push(match typ {
+            1 => format!("{{{}}}", asns.join(",")),
+            _ => asns.join(" "),
+        });
+    }
+    Some(segments.join(" "))
+}
+
+fn bgp_notification(data: &[u8]) -> Option<BgpNotification> {
+    let [code, sub, rest @ ..] = data else {
+        return None;
+    };
+    Some(BgpNotification {
+        code: Code(*code),
+        subcode: *sub,
+        data_len: rest.len(),
+    })
+}
+
+/// Decode a sequence of length prefixed NLRI prefixes.
+fn bgp_prefixes(mut data: &[u8], v6: bool) -> Option<Vec<String>> {
+    let mut prefixes = Vec::new();
+    while let [len, rest @ ..] = data {
+        let n = (*len as usize).div_ceil(8);
+        let bytes = rest.get(..n)?;
+        data = &rest[n..];
+        let prefix = if v6 {
+            let mut addr = [0u8; 16];
+            addr.get_mut(..n)?.copy_from_slice(bytes);
+            Ipv6Addr::from(addr).to_string()
+        } else {
+            let mut addr = [0u8; 4];
+            addr.get_mut(..n)?.copy_from_slice(bytes);
+            Ipv4Addr::from(addr).to_string()
+        };
+        prefixes.push(format!("{}/{}", prefix, len));
+    }
+    Some(prefixes)
+}
+
+fn afi(afi: u16) -> String {
+    match Afi::try_from(afi) {
+        Ok(a) => format!("{:?}", a),
+        _ => format!("{}", afi),
+    }
+}
+
+fn safi_name(safi: u8) -> String {
+    match Safi::try_from(safi) {
+        Ok(s) => format!("{:?}", s),
+        _ => format!("{}", safi),
+    }
+}
+
+const HTTP_METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE",
+    "PATCH",
+];
+
+/// Split an HTTP message into its lines, up to the end of the headers or of
+/// the segment.
+fn http_lines(data: &[u8]) -> impl Iterator<Item = String> + '_ {
+    data.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .take_while(|line| !line.is_empty())
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+}
+
+/// Decode the request or status line and the headers of an HTTP message.
+/// Segments that do not start a message only have their length.
+fn http(data: &[u8]) -> Http {
+    let mut http = Http {
+        len: data.len(),
+        ..Default::default()
+    };
+    let mut lines = http_lines(data);
+    let Some(start) = lines.next() else {
+        return http;
+    };
+    let parts: Vec<&str> = start.splitn(3, ' ').collect();
+    match parts.as_slice() {
+        [version, code, ..]
+            if version.starts_with("HTTP/")
+                && code.len() == 3
+                && code.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            http.status = Some(start[version.len() + 1..].to_string());
+        }
+        [method, path, version]
+            if HTTP_METHODS.contains(method)
+                && version.starts_with("HTTP/") =>
+        {
+            http.method = Some(method.to_string());
+            http.path = Some(path.to_string());
+        }
+        _ => return http,
+    }
+    http.headers = lines
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.to_string(), value.trim().to_string()))
+        })
+        .collect();
+    http
+}
+
+fn geneve<'a>(
+    h: &crate::geneve_h,
+    offset: usize,
+    opts: &'a [u8],
+) -> Geneve<'a> {
+    let (opts, opts_truncated) = geneve_opts(opts);
+    Geneve {
+        offset,
+        vni: h.vni.load_le(),
+        version: h.version.load(),
+        opt_len: h.opt_len.load(),
+        ctrl: bit(&h.ctrl, 0),
+        crit: bit(&h.crit, 0),
+        protocol: Code(h.protocol.load_le()),
+        opts,
+        opts_truncated,
+    }
+}
+
+/// Decode the options that follow the fixed Geneve header, returning them
+/// and whether decoding stopped at a truncated option.
+fn geneve_opts(mut data: &[u8]) -> (Vec<GeneveOpt<'_>>, bool) {
+    let mut opts = Vec::new();
+    while !data.is_empty() {
+        let [a, b, typ, len, rest @ ..] = data else {
+            return (opts, true);
+        };
+        let len = ((len & 0x1f) as usize) << 2;
+        let Some(body) = rest.get(..len) else {
+            return (opts, true);
+        };
+        data = &rest[len..];
+
+        let class = Code::<GeneveOptClass>(u16::from_be_bytes([*a, *b]));
+        let crit = typ & 0x80 != 0;
+        let typ = typ & 0x7f;
+        let oxide = class
+            .known()
+            .map(|GeneveOptClass::Oxide| Code::<OxideOptType>(typ));
+        let mut opt = GeneveOpt {
+            class,
+            typ: match oxide {
+                Some(t) => GeneveOptType::Oxide(t),
+                None => GeneveOptType::Other(typ),
+            },
+            crit,
+            data: body,
+            replication: None,
+            mss: None,
+        };
+        match (oxide.and_then(|t| t.known()), body) {
+            (Some(OxideOptType::Multicast), [b, ..]) => {
+                opt.replication = Some(Code(b >> 6));
+            }
+            (Some(OxideOptType::Mss), [a, b, c, d]) => {
+                opt.mss = Some(u32::from_be_bytes([*a, *b, *c, *d]));
+            }
+            _ => {}
+        }
+        opts.push(opt);
+    }
+    (opts, false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bgp_message(typ: BgpMessageType, body: &[u8]) -> Vec<u8> {
+        let mut m = vec![0xff; 16];
+        m.extend(((BGP_HEADER_LEN + body.len()) as u16).to_be_bytes());
+        m.push(typ as u8);
+        m.extend(body);
+        m
+    }
+
+    fn bgp_open_message() -> Vec<u8> {
+        // AS_TRANS, with the real AS number in the four octet capability.
+        let mut body = vec![4, 0x5b, 0xa0, 0, 90, 10, 0, 0, 1, 14, 2, 12];
+        body.extend([1, 4, 0, 1, 0, 1]);
+        body.extend([65, 4]);
+        body.extend(4_200_000_000u32.to_be_bytes());
+        bgp_message(BgpMessageType::Open, &body)
+    }
+
+    #[test]
+    fn bgp_messages() {
+        let mut update = vec![0, 0, 0, 24];
+        update.extend([0x40, 1, 1, 0]);
+        update.extend([0x40, 2, 10, 2, 2]);
+        update.extend(65000u32.to_be_bytes());
+        update.extend(65001u32.to_be_bytes());
+        update.extend([0x40, 3, 4, 10, 0, 0, 1]);
+        update.extend([24, 192, 168, 1]);
+
+        let mut data = bgp_message(BgpMessageType::Keepalive, &[]);
+        data.extend(bgp_open_message());
+        data.extend(bgp_message(BgpMessageType::Update, &update));
+        data.extend(bgp_message(BgpMessageType::Notification, &[6, 2, 0]));
+        let bgp = bgp(&data);
+        assert_eq!(bgp.continued, 0);
+        assert_eq!(bgp.trailing, 0);
+        assert_eq!(bgp.messages.len(), 4);
+
+        let keepalive = &bgp.messages[0];
+        assert_eq!(keepalive.typ.known(), Some(BgpMessageType::Keepalive));
+        assert_eq!(keepalive.len, BGP_HEADER_LEN);
+        assert!(keepalive.captured.is_none());
+        assert!(keepalive.body.is_none());
+
+        let Some(BgpBody::Open(open)) = &bgp.messages[1].body else {
+            panic!("{:?}", bgp.messages[1]);
+        };
+        assert_eq!(open.version, 4);
+        assert_eq!(open.asn, 4_200_000_000);
+        assert_eq!(open.hold, 90);
+        assert_eq!(open.id, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(open.caps, ["MultiProtocol(Ipv4/Unicast)", "FourOctetAs"]);
+
+        let Some(BgpBody::Update(update)) = &bgp.messages[2].body else {
+            panic!("{:?}", bgp.messages[2]);
+        };
+        assert!(update.withdrawn.is_empty());
+        let attrs: Vec<_> = update
+            .attrs
+            .iter()
+            .map(|a| (a.typ.known(), a.value.as_str()))
+            .collect();
+        assert_eq!(
+            attrs,
+            [
+                (Some(BgpPathAttr::Origin), "Igp"),
+                (Some(BgpPathAttr::AsPath), "65000 65001"),
+                (Some(BgpPathAttr::NextHop), "10.0.0.1"),
+            ]
+        );
+        assert_eq!(update.nlri, ["192.168.1.0/24"]);
+
+        let Some(BgpBody::Notification(n)) = &bgp.messages[3].body else {
+            panic!("{:?}", bgp.messages[3]);
+        };
+        assert_eq!((n.code.0, n.subcode, n.data_len), (6, 2, 1));
+    }
+
+    #[test]
+    fn bgp_across_segments() {
+        // The last message continues in the next segment.
+        let open = bgp_open_message();
+        let mut data = bgp_message(BgpMessageType::Keepalive, &[]);
+        data.extend(&open[..25]);
+        let first = bgp(&data);
+        assert_eq!(first.messages.len(), 2);
+        let msg = &first.messages[1];
+        assert_eq!(msg.typ.known(), Some(BgpMessageType::Open));
+        assert_eq!(msg.len, open.len());
+        assert_eq!(msg.captured, Some(25));
+        assert!(msg.body.is_none());
+
+        // The next segment starts with the rest of it.
+        let mut data = open[25..].to_vec();
+        data.extend(bgp_message(BgpMessageType::Keepalive, &[]));
+        let next = bgp(&data);
+        assert_eq!(next.continued, data.len());
+        assert!(next.messages.is_empty());
+
+        // A header cut short is trailing data.
+        let mut data = bgp_message(BgpMessageType::Keepalive, &[]);
+        data.extend([0xff; 10]);
+        let bgp = bgp(&data);
+        assert_eq!(bgp.messages.len(), 1);
+        assert_eq!(bgp.trailing, 10);
+    }
+
+    #[test]
+    fn bgp_stops_at_unknown_message() {
+        let mut data = vec![0xff; 16];
+        data.extend([0, 19, 9]);
+        data.extend(bgp_message(BgpMessageType::Keepalive, &[]));
+        let bgp = bgp(&data);
+        assert_eq!(bgp.messages.len(), 1);
+        assert_eq!(bgp.messages[0].typ.0, 9);
+        assert_eq!(bgp.trailing, 0);
+    }
+
+    #[test]
+    fn tcp_options() {
+        // The options of the inner TCP segment of the first frame in
+        // data/packet-hex.txt.
+        let opts = tcp_opts(&[
+            0x01, 0x01, 0x08, 0x0a, 0x00, 0xce, 0x89, 0x13, 0x89, 0x14, 0xcb,
+            0x76,
+        ]);
+        assert!(matches!(
+            opts[..],
+            [TcpOpt::Timestamps {
+                value: 0x00ce8913,
+                echo: 0x8914cb76
+            }]
+        ));
+
+        // A SYN, with the options after the end of the list left out.
+        let opts = tcp_opts(&[
+            2, 4, 0x05, 0xb4, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 0, 1, 3, 3, 7,
+            0, 2, 4, 0x05, 0xb4,
+        ]);
+        assert!(matches!(
+            opts[..],
+            [
+                TcpOpt::Mss { mss: 1460 },
+                TcpOpt::SackPermitted,
+                TcpOpt::Timestamps { value: 1, echo: 0 },
+                TcpOpt::WindowScale { shift: 7 },
+            ]
+        ));
+
+        let mut sack = vec![1, 1, 5, 18];
+        for edge in [100u32, 200, 300, 400] {
+            sack.extend(edge.to_be_bytes());
+        }
+        let opts = tcp_opts(&sack);
+        let [TcpOpt::Sack { blocks }] = &opts[..] else {
+            panic!("{:?}", opts);
+        };
+        assert_eq!(blocks, &[(100, 200), (300, 400)]);
+    }
+
+    #[test]
+    fn tcp_bad_options() {
+        let opts = tcp_opts(&[2, 3, 0, 0xfe, 4, 0xaa, 0xbb, 5, 5, 0, 0, 0]);
+        assert!(matches!(
+            &opts[..],
+            [
+                TcpOpt::Malformed { option: mss },
+                TcpOpt::Unknown { code: 0xfe, len: 4 },
+                TcpOpt::Malformed { option: sack },
+            ] if mss.known() == Some(TcpOptKind::Mss)
+                && sack.known() == Some(TcpOptKind::Sack)
+        ));
+
+        // Options running past the end, or too short to hold their length.
+        for data in [&[1, 2, 4, 5][..], &[1, 3], &[3, 1, 0]] {
+            let opts = tcp_opts(data);
+            assert!(matches!(opts[..], [TcpOpt::Truncated]), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn geneve_options() {
+        let data = [
+            // Oxide external, no data.
+            0x01, 0x29, 0x00, 0x00,
+            // Oxide multicast, replicated to all.
+            0x01, 0x29, 0x01, 0x01, 0x80, 0x00, 0x00, 0x00,
+            // Oxide MSS, critical.
+            0x01, 0x29, 0x82, 0x01, 0x00, 0x00, 0x05, 0xdc,
+            // Another class.
+            0x01, 0x02, 0x05, 0x01, 0xde, 0xad, 0xbe, 0xef,
+        ];
+        let (opts, truncated) = geneve_opts(&data);
+        assert!(!truncated);
+        assert_eq!(opts.len(), 4);
+
+        let oxide = |o: &GeneveOpt| match o.typ {
+            GeneveOptType::Oxide(t) => t.known(),
+            GeneveOptType::Other(_) => None,
+        };
+        for o in &opts[..3] {
+            assert_eq!(o.class.known(), Some(GeneveOptClass::Oxide));
+        }
+        assert_eq!(oxide(&opts[0]), Some(OxideOptType::External));
+        assert!(opts[0].data.is_empty());
+        assert!(!opts[0].crit);
+
+        assert_eq!(oxide(&opts[1]), Some(OxideOptType::Multicast));
+        assert_eq!(
+            opts[1].replication.and_then(|r| r.known()),
+            Some(OxideReplication::All)
+        );
+        assert!(opts[1].mss.is_none());
+
+        assert_eq!(oxide(&opts[2]), Some(OxideOptType::Mss));
+        assert!(opts[2].crit);
+        assert_eq!(opts[2].mss, Some(1500));
+
+        assert_eq!(opts[3].class.0, 0x0102);
+        assert!(matches!(opts[3].typ, GeneveOptType::Other(5)));
+        assert_eq!(opts[3].data, [0xde, 0xad, 0xbe, 0xef]);
+        assert!(opts[3].replication.is_none());
+    }
+
+    #[test]
+    fn geneve_truncated_options() {
+        // The second option claims a word of data that is not there.
+        let (opts, truncated) =
+            geneve_opts(&[0x01, 0x29, 0x00, 0x00, 0x01, 0x29, 0x02, 0x01]);
+        assert!(truncated);
+        assert_eq!(opts.len(), 1);
+
+        let (opts, truncated) = geneve_opts(&[0x01, 0x29, 0x00]);
+        assert!(truncated);
+        assert!(opts.is_empty());
+    }
+
+    const TCP: u8 = IpProto::Tcp as u8;
+
+    #[test]
+    fn ipv6_extension_headers() {
+        let mut data = vec![IpProto::Ddm as u8, 0, 5, 2, 0, 0, 1, 0];
+        // The timestamps wrap around between the two elements.
+        data.extend([TCP, 12, 1, 0x80, 1, 0xff, 0xff, 0xf0, 2, 0, 0, 0x10]);
+        data.extend([IpProto::Ipv6Frag as u8, 0, 4, 1, 0, 0, 0, 0]);
+        data.extend([0; 20]);
+        let next = IpProto::Ipv6Hbh as u8;
+
+        // A hop-by-hop header with a router alert, then DDM.
+        let (exts, len) = ipv6_exts(next, &data);
+        assert_eq!(len, 20);
+        let [Ipv6Ext::HopByHop {
+            next_hdr,
+            len: 8,
+            opts,
+        }, Ipv6Ext::Ddm {
+            next_hdr: upper,
+            len: 12,
+            version: 1,
+            ack: true,
+            elements,
+        }] = &exts[..]
+        else {
+            panic!("{:?}", exts);
+        };
+        assert_eq!(next_hdr.known(), Some(IpProto::Ddm));
+        assert_eq!(opts.iter().map(|o| o.0).collect::<Vec<_>>(), [5]);
+        assert_eq!(upper.0, TCP);
+        let elements: Vec<_> = elements
+            .iter()
+            .map(|e| (e.id, e.timestamp, e.delta))
+            .collect();
+        assert_eq!(elements, [(1, 0xfffff0, None), (2, 0x10, Some(0x20))]);
+        assert_eq!(ipv6_upper_layer(next, &data), Some((20, TCP, true)));
+
+        // A routing header.
+        let routing = [TCP, 0, 4, 1, 0, 0, 0, 0, 0, 0];
+        let (exts, len) = ipv6_exts(IpProto::Ipv6Rth as u8, &routing);
+        assert_eq!(len, 8);
+        assert!(matches!(
+            exts[..],
+            [Ipv6Ext::Routing {
+                len: 8,
+                routing_type: 4,
+                segments_left: 1,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn ipv6_fragments() {
+        // The first fragment carries the upper layer header.
+        let first = [TCP, 0, 0, 1, 0, 0, 0, 7, 0, 0];
+        let (exts, len) = ipv6_exts(IpProto::Ipv6Frag as u8, &first);
+        assert_eq!(len, 8);
+        assert!(matches!(
+            exts[..],
+            [Ipv6Ext::Fragment {
+                offset: 0,
+                more: true,
+                id: 7,
+                ..
+            }]
+        ));
+        let next = IpProto::Ipv6Frag as u8;
+        assert_eq!(ipv6_upper_layer(next, &first), Some((8, TCP, false)));
+
+        // Later ones do not, so the walk stops even though the next header
+        // is an extension header.
+        let later = [0, 0, 0x05, 0xa8, 0, 0, 0, 7, 0, 0];
+        let (exts, len) = ipv6_exts(next, &later);
+        assert_eq!(len, 8);
+        assert!(matches!(
+            exts[..],
+            [Ipv6Ext::Fragment {
+                offset: 0x5a8,
+                more: false,
+                ..
+            }]
+        ));
+        assert_eq!(ipv6_upper_layer(next, &later), Some((8, 0, false)));
+    }
+
+    #[test]
+    fn ipv6_truncated_extension_headers() {
+        let ddm = IpProto::Ddm as u8;
+        for data in [
+            // Shorter than the fixed part.
+            &[TCP, 3, 1, 0][..],
+            // Not a whole number of elements.
+            &[TCP, 6, 1, 0, 1, 0],
+            // Longer than the packet.
+            &[TCP, 12, 1, 0, 1, 0, 0, 0],
+        ] {
+            let (exts, len) = ipv6_exts(ddm, data);
+            assert_eq!(len, 0, "{:?}", data);
+            assert!(matches!(exts[..], [Ipv6Ext::Truncated]), "{:?}", data);
+            assert_eq!(ipv6_upper_layer(ddm, data), None);
+        }
+
+        // A hop-by-hop header followed by one cut short.
+        let data = [IpProto::Ipv6DstOpt as u8, 0, 1, 4, 0, 0, 0, 0, TCP, 1];
+        let (exts, len) = ipv6_exts(IpProto::Ipv6Hbh as u8, &data);
+        assert_eq!(len, 8);
+        assert!(matches!(
+            exts[..],
+            [Ipv6Ext::HopByHop { .. }, Ipv6Ext::Truncated]
+        ));
+    }
+
+    #[test]
+    fn http_request() {
+        let data = b"GET /metrics HTTP/1.1\r\nHost: example\r\n\
+            Accept:  */*\r\n\r\nX-Not-A-Header: body";
+        let http = http(data);
+        assert!(http.is_start());
+        assert_eq!(http.method.as_deref(), Some("GET"));
+        assert_eq!(http.path.as_deref(), Some("/metrics"));
+        assert!(http.status.is_none());
+        assert_eq!(
+            http.headers,
+            [
+                ("Host".to_string(), "example".to_string()),
+                ("Accept".to_string(), "*/*".to_string()),
+            ]
+        );
+        assert_eq!(http.len, data.len());
+    }
+
+    #[test]
+    fn http_response() {
+        // Bare line feeds, and headers that continue in the next segment.
+        let data = b"HTTP/1.1 404 Not Found\nServer: ddm\nContent-Le";
+        let http = http(data);
+        assert!(http.is_start());
+        assert!(http.method.is_none());
+        assert_eq!(http.status.as_deref(), Some("404 Not Found"));
+        assert_eq!(http.headers, [("Server".to_string(), "ddm".to_string())]);
+    }
+
+    #[test]
+    fn http_continued() {
+        for data in [
+            &b"ngth: 2\r\n\r\nok"[..],
+            b"GET /",
+            b"HTTP/1.1 20 OK\r\n",
+            b"",
+        ] {
+            let http = http(data);
+            assert!(!http.is_start(), "{:?}", data);
+            assert!(http.headers.is_empty());
+            assert_eq!(http.len, data.len());
+        }
+    }
+
+    /// An Ethernet frame carrying an IPv4 TCP or UDP segment.
+    fn ipv4_frame(proto: u8, src: u16, dst: u16, payload: &[u8]) -> Vec<u8> {
+        let mut l4 = [src.to_be_bytes(), dst.to_be_bytes()].concat();
+        if proto == TCP {
+            l4.extend([0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff]);
+            l4.extend([0, 0, 0, 0]);
+        } else {
+            l4.extend(((8 + payload.len()) as u16).to_be_bytes());
+            l4.extend([0, 0]);
+        }
+        l4.extend(payload);
+
+        let mut f = vec![0xa8, 0x40, 0x25, 0, 0, 2, 0xa8, 0x40, 0x25, 0, 0, 1];
+        f.extend((Ethertype::IPv4 as u16).to_be_bytes());
+        f.extend([0x45, 0]);
+        f.extend(((20 + l4.len()) as u16).to_be_bytes());
+        f.extend([0, 1, 0x40, 0, 64, proto, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        f.extend(l4);
+        f
+    }
+
+    #[test]
+    fn parser_and_decoder_agree() {
+        use crate::cli::Filter;
+        use crate::dump::Alp;
+        use crate::pipeline::Pipeline;
+
+        let keepalive = bgp_message(BgpMessageType::Keepalive, &[]);
+        let get = b"GET / HTTP/1.1\r\n\r\n";
+        let mut cases = vec![
+            (Alp::Bgp, TCP, BGP_PORT, &keepalive[..]),
+            (Alp::DdmExchange, TCP, DDM_EXCHANGE_PORT, get),
+            (Alp::BfdEcho, IpProto::Udp as u8, BFD_ECHO_PORT, b"echo"),
+        ];
+        for port in HTTP_PORTS {
+            cases.push((Alp::Http, TCP, *port, b"HTTP/1.1 200 OK\r\n\r\n"));
+        }
+
+        for (alp, proto, port, payload) in cases {
+            let mut pipeline = Pipeline::new(&Filter {
+                alp: vec![alp],
+                ..Default::default()
+            })
+            .unwrap();
+            // The server port is the destination of requests and the source
+            // of responses.
+            let mut frames = vec![ipv4_frame(proto, 40000, port, payload)];
+            if proto == TCP {
+                frames.push(ipv4_frame(proto, port, 40000, payload));
+            }
+            for frame in frames {
+                let Some(m) = pipeline.process(&frame) else {
+                    panic!("{:?} on port {} not kept", alp, port);
+                };
+                let decoded = match &m.packets[0].app {
+                    Some(App::Bgp(_)) => Alp::Bgp,
+                    Some(App::DdmExchange(_)) => Alp::DdmExchange,
+                    Some(App::BfdEcho { .. }) => Alp::BfdEcho,
+                    Some(App::Http(_)) => Alp::Http,
+                    app => panic!("port {} decoded as {:?}", port, app),
+                };
+                assert_eq!(decoded, alp, "port {}", port);
+            }
+        }
+    }
+}
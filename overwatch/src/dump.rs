@@ -1,13 +1,20 @@
 // Copyright 2023 Oxide Computer Company
 
+use crate::cli::TimeFormat;
+use crate::decode::{
+    App, Arp, Bfd, BfdAuth, Bgp, BgpBody, BgpNotification, BgpOpen, BgpUpdate,
+    Checksum, Code, DdmDiscovery, DdmElement, DecodedPacket, Echo, Eth, Geneve,
+    GeneveOpt, GeneveOptType, Http, Icmp, Ipv4, Ipv6, Ipv6Ext, Lldp, Sidecar,
+    Tcp, TcpOpt, Udp, Vlan, BGP_HEADER_LEN, L4,
+};
 use anyhow::{anyhow, Result};
 use bitvec::prelude::*;
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use macaddr::MacAddr6;
 use num_enum::TryFromPrimitive;
-use p4rs::{checksum::udp6_checksum, Header};
 use pretty_hex::*;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn bv_to_mac(bv: BitVec<u8, Msb0>) -> Result<MacAddr6> {
     let mut m: Vec<u8> = bv.into_vec();
@@ -237,6 +244,8 @@ pub enum IpProto {
     //Shim6 = 140,
     //WESP = 141,
     //ROHC = 142,
+    /// Delay driven multipath extension header, see p4/parse.p4.
+    Ddm = 0xdd,
     //ExperimentalAndTesting0 = 253,
     //ExperimentalAndTesting1 = 254,
 }
@@ -254,6 +263,7 @@ pub enum Alp {
     DdmDiscovery = 0x4,
     DdmExchange = 0x5,
     Bfd = 0x6,
+    BfdEcho = 0x7,
 }
 
 #[allow(clippy::upper_case_acronyms)]
@@ -468,6 +478,17 @@ pub enum Icmp6NodeInformationResponseCode {
     QtypeUnknown = 2,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Ipv6OptType {
+    Pad1 = 0x00,
+    PadN = 0x01,
+    TunnelEncapLimit = 0x04,
+    RouterAlert = 0x05,
+    Jumbo = 0xc2,
+    HomeAddress = 0xc9,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
 #[repr(u8)]
 pub enum BfdDiagnostic {
@@ -491,12 +512,207 @@ pub enum BfdStatus {
     Up = 3,
 }
 
+#[derive(
+    Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive, clap::ValueEnum,
+)]
+#[repr(u16)]
+pub enum GeneveOptClass {
+    Oxide = 0x0129,
+}
+
+/// Option types of the Oxide option class, as used by OPTE.
+#[derive(
+    Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive, clap::ValueEnum,
+)]
+#[repr(u8)]
+pub enum OxideOptType {
+    External = 0x00,
+    Multicast = 0x01,
+    Mss = 0x02,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum OxideReplication {
+    External = 0,
+    Underlay = 1,
+    All = 2,
+    Reserved = 3,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BgpMessageType {
+    Open = 1,
+    Update = 2,
+    Notification = 3,
+    Keepalive = 4,
+    RouteRefresh = 5,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BgpCapability {
+    MultiProtocol = 1,
+    RouteRefresh = 2,
+    ExtendedNextHop = 5,
+    GracefulRestart = 64,
+    FourOctetAs = 65,
+    AddPath = 69,
+    EnhancedRouteRefresh = 70,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BgpPathAttr {
+    Origin = 1,
+    AsPath = 2,
+    NextHop = 3,
+    Med = 4,
+    LocalPref = 5,
+    AtomicAggregate = 6,
+    Aggregator = 7,
+    Communities = 8,
+    MpReachNlri = 14,
+    MpUnreachNlri = 15,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BgpOrigin {
+    Igp = 0,
+    Egp = 1,
+    Incomplete = 2,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BgpErrorCode {
+    MessageHeaderError = 1,
+    OpenMessageError = 2,
+    UpdateMessageError = 3,
+    HoldTimerExpired = 4,
+    FsmError = 5,
+    Cease = 6,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u16)]
+pub enum Afi {
+    Ipv4 = 1,
+    Ipv6 = 2,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Safi {
+    Unicast = 1,
+    Multicast = 2,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum BfdAuthType {
+    SimplePassword = 1,
+    KeyedMd5 = 2,
+    MeticulousKeyedMd5 = 3,
+    KeyedSha1 = 4,
+    MeticulousKeyedSha1 = 5,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum TcpOptKind {
+    Eol = 0,
+    Nop = 1,
+    Mss = 2,
+    WindowScale = 3,
+    SackPermitted = 4,
+    Sack = 5,
+    Timestamps = 8,
+    Md5 = 19,
+    Ao = 29,
+}
+
 pub fn sep() {
     println!("{}", "=====|".dimmed());
 }
 
-pub fn frame(h: crate::headers_t, frame: &[u8], dump_hex: bool) {
-    headers(h, frame);
+/// Formats capture times, keeping track of the frames shown so far for
+/// relative and delta times.
+pub struct Clock {
+    format: TimeFormat,
+    first: Option<SystemTime>,
+    prev: Option<SystemTime>,
+}
+
+impl Clock {
+    pub fn new(format: TimeFormat) -> Self {
+        Self {
+            format,
+            first: None,
+            prev: None,
+        }
+    }
+
+    /// Format the capture time of the next frame shown.
+    pub fn time(&mut self, ts: SystemTime) -> String {
+        let first = *self.first.get_or_insert(ts);
+        let prev = self.prev.replace(ts).unwrap_or(ts);
+        match self.format {
+            TimeFormat::Absolute => utc(ts),
+            TimeFormat::Relative => elapsed(first, ts),
+            TimeFormat::Delta => elapsed(prev, ts),
+        }
+    }
+}
+
+/// Seconds from `from` to `to`, negative when frames were captured out of
+/// order.
+fn elapsed(from: SystemTime, to: SystemTime) -> String {
+    let (sign, d) = match to.duration_since(from) {
+        Ok(d) => ("", d),
+        Err(e) => ("-", e.duration()),
+    };
+    format!("{}{}.{:06}", sign, d.as_secs(), d.subsec_micros())
+}
+
+/// Date and time in UTC, with microseconds.
+fn utc(ts: SystemTime) -> String {
+    let d = ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, secs) = (d.as_secs() / 86400, d.as_secs() % 86400);
+
+    // Days since the epoch to a civil date, counting years from March so
+    // leap days fall at the end of the year.
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        d.subsec_micros(),
+    )
+}
+
+/// Show a decoded packet, one line per header, preceded by its capture time
+/// if provided.
+pub fn frame(p: &DecodedPacket, time: Option<&str>, dump_hex: bool) {
+    if let Some(t) = time {
+        capture_time(t);
+    }
+    headers(p);
     if dump_hex {
         let cfg = HexConfig {
             title: false,
@@ -504,128 +720,57 @@ pub fn frame(h: crate::headers_t, frame: &[u8], dump_hex: bool) {
             chunk: 2,
             ..HexConfig::default()
         };
-        let dump = format!("-----| {:?}", frame.hex_conf(cfg));
+        let dump = format!("-----| {:?}", p.frame.hex_conf(cfg));
         let dump = dump.replace('\n', "\n     | ");
         println!("{}", dump.dimmed());
     }
     sep();
 }
 
-macro_rules! hlen {
-    ($hdr:tt) => {
-        crate::$hdr::size() >> 3
-    };
-}
-
-#[allow(unused_assignments)]
-pub fn headers(h: crate::headers_t, frame: &[u8]) {
-    let mut off = 0usize;
-    let mut v6_start = None;
-    if h.ethernet.isValid() {
-        ethernet(h.ethernet, Some(frame.len()));
-        off += hlen!(ethernet_h);
-    }
-    if h.vlan.isValid() {
-        vlan(h.vlan);
-        off += hlen!(vlan_h);
+fn headers(p: &DecodedPacket) {
+    if let Some(h) = &p.eth {
+        ethernet(h, Some(p.frame.len()));
     }
-    if h.sidecar.isValid() {
-        sidecar(h.sidecar);
-        off += hlen!(sidecar_h);
+    if let Some(h) = &p.vlan {
+        vlan(h);
     }
-    if h.lldp.isValid() {
-        lldp(&frame[off..]);
+    if let Some(h) = &p.sidecar {
+        sidecar(h);
     }
-    if h.arp.isValid() {
-        arp(h.arp);
-        off += hlen!(arp_h);
-    }
-    if h.ipv4.isValid() {
-        let ihl: u8 = h.ipv4.ihl.load();
-        ipv4(h.ipv4);
-        off += (ihl << 2) as usize;
-        if h.icmp.isValid() {
-            icmp(h.icmp);
-            off += hlen!(icmp_h);
-            if h.echo.isValid() {
-                echo(h.echo);
-                off += hlen!(echo_h);
-            }
-        }
-    } else if h.ipv6.isValid() {
-        v6_start = Some(off);
-        ipv6(h.ipv6);
-        if h.icmp.isValid() {
-            icmp6(h.icmp);
-            off += hlen!(icmp_h);
-            if h.echo.isValid() {
-                echo(h.echo);
-                off += hlen!(echo_h);
-            }
-        }
-        off += hlen!(ipv6_h);
+    if let Some(h) = &p.lldp {
+        lldp(h);
     }
-    if h.tcp.isValid() {
-        let len: u8 = h.tcp.data_offset.load();
-        tcp(h.tcp);
-        off += (len << 2) as usize;
+    if let Some(h) = &p.arp {
+        arp(h);
     }
-    if h.udp.isValid() {
-        let csum = v6_start.map(|off| udp6_checksum(&frame[off..]));
-        udp(h.udp, csum);
-        off += hlen!(udp_h);
+    if let Some(h) = &p.ipv4 {
+        ipv4(h);
+    } else if let Some(h) = &p.ipv6 {
+        ipv6(h);
     }
-    if h.ddm_discovery.isValid() {
-        ddm_discovery(h.ddm_discovery, &frame[off..]);
-        off += hlen!(ddm_discovery_h);
+    if let Some(h) = &p.l4 {
+        l4(h);
     }
-    if h.bfd.isValid() {
-        bfd(h.bfd);
-        off += hlen!(bfd_h);
+    if let Some(a) = &p.app {
+        app(a);
     }
-    if h.geneve.isValid() {
-        geneve(h.geneve);
+    if let Some(h) = &p.geneve {
+        geneve(h);
         println!("{}", "-----|".dimmed());
-        off += hlen!(geneve_h);
     }
-    if h.inner_eth.isValid() {
-        ethernet(h.inner_eth, None);
+    if let Some(h) = &p.inner_eth {
+        ethernet(h, None);
     }
-    if h.inner_arp.isValid() {
-        arp(h.inner_arp);
-        off += hlen!(arp_h);
+    if let Some(h) = &p.inner_arp {
+        arp(h);
     }
-    if h.inner_ipv4.isValid() {
-        let ihl: u8 = h.inner_ipv4.ihl.load();
-        ipv4(h.inner_ipv4);
-        off += (ihl << 2) as usize;
-        if h.inner_icmp.isValid() {
-            icmp(h.inner_icmp);
-            off += hlen!(icmp_h);
-            if h.inner_echo.isValid() {
-                echo(h.inner_echo);
-                off += hlen!(echo_h);
-            }
-        }
-    } else if h.inner_ipv6.isValid() {
-        ipv6(h.inner_ipv6);
-        if h.inner_icmp.isValid() {
-            icmp6(h.inner_icmp);
-            off += hlen!(icmp_h);
-            if h.inner_echo.isValid() {
-                echo(h.inner_echo);
-                off += hlen!(echo_h);
-            }
-        }
+    if let Some(h) = &p.inner_ipv4 {
+        ipv4(h);
+    } else if let Some(h) = &p.inner_ipv6 {
+        ipv6(h);
     }
-    if h.inner_tcp.isValid() {
-        let len: u8 = h.inner_tcp.data_offset.load();
-        tcp(h.inner_tcp);
-        off += (len << 2) as usize;
-    }
-    if h.inner_udp.isValid() {
-        udp(h.inner_udp, None);
-        off += hlen!(udp_h);
+    if let Some(h) = &p.inner_l4 {
+        l4(h);
     }
 }
 
@@ -678,19 +823,33 @@ macro_rules! resolve {
     };
 }
 
-pub fn ethernet(h: crate::ethernet_h, frame_len: Option<usize>) {
-    let Ok(dst) = bv_to_mac(h.dst) else { return };
-    let Ok(src) = bv_to_mac(h.src) else { return };
-    let et: u16 = h.ether_type.load_le();
-    let et = match Ethertype::try_from(et) {
-        Ok(h) => format!("{:?}", h).green(),
-        _ => format!("0x{:04x}", et).green(),
-    };
+/// Show a checksum, flagged if it differs from the expected value.
+fn checksum(chk: &Checksum) -> String {
+    match chk.expected {
+        Some(expected) if chk.is_bad() => {
+            bad_field!("chk", chk.value, expected)
+        }
+        _ => field!("chk", chk.value),
+    }
+}
+
+fn ether_type(et: Code<Ethertype>) -> ColoredString {
+    match et.known() {
+        Some(e) => format!("{:?}", e).green(),
+        None => format!("0x{:04x}", et.0).green(),
+    }
+}
+
+fn capture_time(t: &str) {
+    println!("{} {}", layer!("Time"), t);
+}
+
+fn ethernet(h: &Eth, frame_len: Option<usize>) {
     print!(
         "{} {} {}",
         layer!("Eth"),
-        from_to!(src, dst),
-        field!("et", et),
+        from_to!(h.src, h.dst),
+        field!("et", ether_type(h.ether_type)),
     );
     if let Some(n) = frame_len {
         println!(" {}", field!("len", n));
@@ -699,304 +858,376 @@ pub fn ethernet(h: crate::ethernet_h, frame_len: Option<usize>) {
     }
 }
 
-pub fn vlan(h: crate::vlan_h) {
-    let pcp: u8 = h.pcp.load();
-    let dei: bool = *h.dei.get(0).unwrap();
-    let vid: u16 = h.vid.load_le();
-    let et: u16 = h.ether_type.load_le();
-    let et = match Ethertype::try_from(et) {
-        Ok(h) => format!("{:?}", h).green(),
-        _ => format!("0x{:04x}", et).green(),
-    };
-
+fn vlan(h: &Vlan) {
     println!(
         "{} {} {} {} {}",
         layer!("Vlan"),
-        vid.to_string().blue(),
-        field!("pcp", pcp),
-        field!("dei", dei),
-        field!("et", et),
+        h.vid.to_string().blue(),
+        field!("pcp", h.pcp),
+        field!("dei", h.dei),
+        field!("et", ether_type(h.ether_type)),
     );
 }
 
-pub fn lldp(data: &[u8]) {
-    match lldp::types::Lldpdu::try_from(data) {
-        Ok(l) => {
-            let label = layer!("Lldp");
-            let space = layer!("");
-            println!("{} {}", label, field!("ChassisID", l.chassis_id));
-            println!("{} {}", space, field!("PortId", l.port_id));
-            println!("{} {}", space, field!("TTL", l.ttl));
-            if let Some(s) = &l.port_description {
-                println!("{} {}", space, field!("PortDescription", s));
-            }
-            if let Some(s) = &l.system_name {
-                println!("{} {}", space, field!("System Name", s));
-            }
-            if let Some(s) = &l.system_description {
-                println!("{} {}", space, field!("System Description", s));
-            }
-            if !l.management_addresses.is_empty() {
-                println!(
-                    "{} {}",
-                    space,
-                    "Management addresses:".to_string().dimmed()
-                );
-                for ma in &l.management_addresses {
-                    println!("{space}\t{ma:?}");
-                }
-            }
-            if !l.organizationally_specific.is_empty() {
-                println!(
-                    "{} {}",
-                    space,
-                    "Organizationally Specific:".to_string().dimmed()
-                );
-                for os in &l.organizationally_specific {
-                    println!("{space}\t{os}");
-                }
-            }
+fn lldp(h: &Lldp) {
+    let Lldp::Decoded {
+        chassis_id,
+        port_id,
+        ttl,
+        port_description,
+        system_name,
+        system_description,
+        management_addresses,
+        organizationally_specific,
+    } = h
+    else {
+        if let Lldp::Malformed { error } = h {
+            println!("<unable to parse lldp packet: {error}>");
+        }
+        return;
+    };
+    let label = layer!("Lldp");
+    let space = layer!("");
+    println!("{} {}", label, field!("ChassisID", chassis_id));
+    println!("{} {}", space, field!("PortId", port_id));
+    println!("{} {}", space, field!("TTL", ttl));
+    if let Some(s) = port_description {
+        println!("{} {}", space, field!("PortDescription", s));
+    }
+    if let Some(s) = system_name {
+        println!("{} {}", space, field!("System Name", s));
+    }
+    if let Some(s) = system_description {
+        println!("{} {}", space, field!("System Description", s));
+    }
+    if !management_addresses.is_empty() {
+        println!("{} {}", space, "Management addresses:".to_string().dimmed());
+        for ma in management_addresses {
+            println!("{space}\t{ma}");
+        }
+    }
+    if !organizationally_specific.is_empty() {
+        println!(
+            "{} {}",
+            space,
+            "Organizationally Specific:".to_string().dimmed()
+        );
+        for os in organizationally_specific {
+            println!("{space}\t{os}");
         }
-        Err(e) => println!("<unable to parse lldp packet: {e}>"),
     }
 }
 
-pub fn sidecar(h: crate::sidecar_h) {
-    let sc: u8 = h.sc_code.load();
-    let sc = match SidecarCode::try_from(sc) {
-        Ok(h) => format!("{:?}", h),
-        _ => format!("0x{:02x}", sc),
-    };
-
-    let sc_ingress: u16 = h.sc_ingress.load_le();
-    let sc_egress: u16 = h.sc_egress.load_le();
-
-    let et: u16 = h.sc_ether_type.load_le();
-    let et = match Ethertype::try_from(et) {
-        Ok(h) => format!("{:?}", h).green(),
-        _ => format!("0x{:04x}", et).green(),
+fn sidecar(h: &Sidecar) {
+    let sc = match h.code.known() {
+        Some(c) => format!("{:?}", c),
+        None => format!("0x{:02x}", h.code.0),
     };
-
     println!(
         "{} {} {} {} {}",
         layer!("Sc"),
         sc,
-        field!("ingress", sc_ingress),
-        field!("egress", sc_egress),
-        field!("et", et),
+        field!("ingress", h.ingress),
+        field!("egress", h.egress),
+        field!("et", ether_type(h.ether_type)),
     );
 }
 
-pub fn arp(h: crate::arp_h) {
-    let ht: u16 = h.hw_type.load_le();
-    let ht = match ArpHwType::try_from(ht) {
-        Ok(h) => format!("{:?}", h),
-        _ => format!("0x{:04x}", ht),
-    };
-    let pt: u16 = h.proto_type.load_le();
-    let pt = match Ethertype::try_from(pt) {
-        Ok(h) => format!("{:?}", h).green(),
-        _ => format!("0x{:04x}", pt).green(),
-    };
-    let hlen: u8 = h.hw_addr_len.load();
-    let plen: u8 = h.proto_addr_len.load();
-    let opcode: u16 = h.opcode.load_le();
-    let opcode = match ArpOpcode::try_from(opcode) {
-        Ok(h) => format!("{:?}", h),
-        _ => format!("0x{:04x}", opcode),
+fn arp(h: &Arp) {
+    let ht = match h.hw_type.known() {
+        Some(t) => format!("{:?}", t),
+        None => format!("0x{:04x}", h.hw_type.0),
     };
-
-    let Ok(sender_mac) = bv_to_mac(h.sender_mac) else {
-        return;
-    };
-    let Ok(sender_ip) = bv_to_ipv4(h.sender_ip) else {
-        return;
+    let opcode = match h.opcode.known() {
+        Some(o) => format!("{:?}", o),
+        None => format!("0x{:04x}", h.opcode.0),
     };
-    let Ok(target_mac) = bv_to_mac(h.target_mac) else {
-        return;
-    };
-    let Ok(target_ip) = bv_to_ipv4(h.target_ip) else {
-        return;
-    };
-
     println!(
         "{} {} {} {} {} {} {}",
         layer!("Arp"),
-        resolve!(sender_ip, sender_mac, target_ip, target_mac),
+        resolve!(h.sender_ip, h.sender_mac, h.target_ip, h.target_mac),
         field!("op", opcode),
         field!("ht", ht),
-        field!("pt", pt),
-        field!("hlen", hlen),
-        field!("plen", plen),
+        field!("pt", ether_type(h.proto_type)),
+        field!("hlen", h.hw_addr_len),
+        field!("plen", h.proto_addr_len),
     );
 }
 
-pub fn ipv4(h: crate::ipv4_h) {
-    let Ok(src) = bv_to_ipv4(h.src) else { return };
-    let Ok(dst) = bv_to_ipv4(h.dst) else { return };
-    let _ver: u8 = h.version.load();
-    let ihl: u8 = h.ihl.load();
-    let ds: u8 = h.diffserv.load();
-    let len: u16 = h.total_len.load_le();
-    let id: u16 = h.identification.load_le();
-
-    let flags: u8 = h.flags.load();
-    let flags = match flags {
+fn ipv4(h: &Ipv4) {
+    let flags = match h.flags {
         0b010 => "DF",
         0b001 => "MF",
         0b011 => "DF|MF",
         _ => "",
     };
-
-    let frag_off: u16 = h.frag_offset.load_le();
-    let ttl: u8 = h.ttl.load();
-
-    let proto: u8 = h.protocol.load();
-    let proto = match IpProto::try_from(proto) {
-        Ok(proto) => format!("{:?}", proto).green().to_string(),
-        _ => format!("{}", proto),
-    };
-
-    let chk: u16 = h.hdr_checksum.load_le();
-
     println!(
         "{} {} {} {} {} {} {} {} {} {} {}",
         layer!("Ip4"),
-        from_to!(src, dst),
-        //field!("ver", ver),
-        field!("ihl", ihl),
-        field!("ds", ds),
-        field!("len", len),
-        field!("id", id),
+        from_to!(h.src, h.dst),
+        field!("ihl", h.ihl),
+        field!("ds", h.diffserv),
+        field!("len", h.total_len),
+        field!("id", h.id),
         field!("flags", flags),
-        field!("fo", frag_off),
-        field!("ttl", ttl),
-        field!("chk", chk),
-        field!("proto", proto),
+        field!("fo", h.frag_offset),
+        field!("ttl", h.ttl),
+        checksum(&h.checksum),
+        field!("proto", ip_proto(h.protocol)),
     );
 }
 
-pub fn ipv6(h: crate::ipv6_h) {
-    let Ok(src) = bv_to_ipv6(h.src) else { return };
-    let Ok(dst) = bv_to_ipv6(h.dst) else { return };
-    let _ver: u8 = h.version.load();
-    let tc: u8 = h.traffic_class.load();
-    let fl: u32 = h.flow_label.load_le();
-    let len: u16 = h.payload_len.load_le();
-
-    let proto: u8 = h.next_hdr.load();
-    let proto = match IpProto::try_from(proto) {
-        Ok(proto) => format!("{:?}", proto).green().to_string(),
-        _ => format!("{}", proto),
-    };
-
-    let ttl: u8 = h.hop_limit.load();
-
+fn ipv6(h: &Ipv6) {
     println!(
         "{} {} {} {} {} {} {}",
         layer!("Ip6"),
-        from_to!(src, dst),
-        //field!("ver", ver),
-        field!("tc", tc),
-        field!("fl", fl),
-        field!("len", len),
-        field!("ttl", ttl),
-        field!("proto", proto),
-    )
+        from_to!(h.src, h.dst),
+        field!("tc", h.traffic_class),
+        field!("fl", h.flow_label),
+        field!("len", h.payload_len),
+        field!("ttl", h.hop_limit),
+        field!("proto", ip_proto(h.next_hdr)),
+    );
+    for ext in &h.ext {
+        ipv6_ext(ext);
+    }
 }
 
-pub fn tcp(h: crate::tcp_h) {
-    let src: u16 = h.src_port.load_le();
-    let dst: u16 = h.dst_port.load_le();
-    let seq: u32 = h.seq_no.load_le();
-    let ack: u32 = h.ack_no.load_le();
-    let off: u8 = h.data_offset.load();
-    let res: u8 = h.res.load();
-
-    let mut flags = Vec::new();
-    if let Some(&true) = h.flags.get(0).as_deref() {
-        flags.push("CWR");
-    }
-    if let Some(&true) = h.flags.get(1).as_deref() {
-        flags.push("ECE");
+fn ip_proto(proto: Code<IpProto>) -> String {
+    match proto.known() {
+        Some(p) => format!("{:?}", p).green().to_string(),
+        None => format!("{}", proto.0),
     }
-    if let Some(&true) = h.flags.get(2).as_deref() {
-        flags.push("URG");
-    }
-    if let Some(&true) = h.flags.get(4).as_deref() {
-        flags.push("PSH");
+}
+
+fn ipv6_ext(ext: &Ipv6Ext) {
+    match ext {
+        Ipv6Ext::HopByHop {
+            next_hdr,
+            len,
+            opts,
+        } => ipv6_opts("HBH", *next_hdr, *len, opts),
+        Ipv6Ext::DstOpts {
+            next_hdr,
+            len,
+            opts,
+        } => ipv6_opts("DstO", *next_hdr, *len, opts),
+        Ipv6Ext::Routing {
+            next_hdr,
+            len,
+            routing_type,
+            segments_left,
+        } => println!(
+            "{} {} {} {} {}",
+            layer!("Rth"),
+            field!("next", ip_proto(*next_hdr)),
+            field!("len", len),
+            field!("type", routing_type),
+            field!("left", segments_left),
+        ),
+        Ipv6Ext::Fragment {
+            next_hdr,
+            offset,
+            more,
+            id,
+        } => println!(
+            "{} {} {} {} {}",
+            layer!("Frag"),
+            field!("next", ip_proto(*next_hdr)),
+            field!("off", offset),
+            field!("more", more),
+            field!("id", id),
+        ),
+        Ipv6Ext::Ddm {
+            next_hdr,
+            len,
+            version,
+            ack,
+            elements,
+        } => ddm(*next_hdr, *len, *version, *ack, elements),
+        Ipv6Ext::Truncated => {
+            println!("{} {}", layer!("Ext6"), "<truncated header>".red())
+        }
     }
-    if let Some(&true) = h.flags.get(5).as_deref() {
-        flags.push("RST");
+}
+
+/// Show a hop-by-hop or destination options header.
+fn ipv6_opts(
+    label: &str,
+    next: Code<IpProto>,
+    len: usize,
+    opts: &[Code<Ipv6OptType>],
+) {
+    let opts: Vec<String> = opts
+        .iter()
+        .map(|o| match o.known() {
+            Some(t) => format!("{:?}", t),
+            None => format!("0x{:02x}", o.0),
+        })
+        .collect();
+    print!(
+        "{} {} {}",
+        layer!(label),
+        field!("next", ip_proto(next)),
+        field!("len", len),
+    );
+    if opts.is_empty() {
+        println!();
+    } else {
+        println!(" {}", field!("opts", opts.join("|")));
     }
-    if let Some(&true) = h.flags.get(6).as_deref() {
-        flags.push("SYN");
+}
+
+/// Show a DDM extension header. Elements after the first show the time
+/// elapsed since the previous one.
+fn ddm(
+    next: Code<IpProto>,
+    len: usize,
+    version: u8,
+    ack: bool,
+    elements: &[DdmElement],
+) {
+    println!(
+        "{} {} {} {} {}",
+        layer!("DDM"),
+        field!("next", ip_proto(next)),
+        field!("len", len),
+        field!("version", version),
+        field!("ack", ack),
+    );
+    for e in elements {
+        print!(
+            "{} {} {}",
+            layer!(""),
+            field!("id", e.id),
+            field!("ts", e.timestamp)
+        );
+        match e.delta {
+            Some(d) => println!(" {}", field!("delta", format!("+{}", d))),
+            None => println!(),
+        }
     }
-    if let Some(&true) = h.flags.get(3).as_deref() {
-        flags.push("ACK");
+}
+
+fn l4(h: &L4) {
+    match h {
+        L4::Tcp(h) => tcp(h),
+        L4::Udp(h) => udp(h),
+        L4::Icmp(h) => icmp(h),
+        L4::Icmp6(h) => icmp6(h),
     }
-    if let Some(&true) = h.flags.get(7).as_deref() {
-        flags.push("FIN");
+}
+
+fn app(a: &App) {
+    match a {
+        App::Bfd(b) => bfd(b),
+        App::BfdEcho { len } => bfd_echo(*len),
+        App::DdmDiscovery(d) => ddm_discovery(d),
+        App::DdmExchange(h) => http_message("DDMx", h),
+        App::Bgp(b) => bgp(b),
+        App::Http(h) => http_message("HTTP", h),
     }
-    let flags = flags.join("|");
+}
 
-    let win: u16 = h.window.load_le();
-    let chk: u16 = h.checksum.load_le();
-    let urg: u16 = h.urgent_ptr.load_le();
+/// Headers worth showing, the rest are omitted.
+const HTTP_HEADERS: &[&str] = &[
+    "host",
+    "content-type",
+    "content-length",
+    "transfer-encoding",
+    "location",
+];
+
+/// Show the request or status line and the key headers of an HTTP message.
+/// Segments that do not start a message only show their length.
+fn http_message(label: &str, h: &Http) {
+    let start = match (&h.method, &h.path, &h.status) {
+        (_, _, Some(status)) => field!("status", status.green()),
+        (Some(method), Some(path), _) => {
+            format!("{} {}", method.green(), path.blue())
+        }
+        _ => {
+            println!("{} {}", layer!(label), field!("len", h.len));
+            return;
+        }
+    };
+    println!("{} {} {}", layer!(label), start, field!("len", h.len));
+    for (name, value) in &h.headers {
+        if HTTP_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            println!("{} {}", layer!(""), field!(name, value));
+        }
+    }
+}
 
-    println!(
+fn tcp(h: &Tcp) {
+    print!(
         "{} {} {} {} {} {} {} {} {} {}",
         layer!("TCP"),
-        from_to!(src, dst),
-        field!("seq", seq),
-        field!("ack", ack),
-        field!("off", off),
-        field!("res", res),
-        field!("flags", flags),
-        field!("win", win),
-        field!("chk", chk),
-        field!("urg", urg),
+        from_to!(h.src_port, h.dst_port),
+        field!("seq", h.seq),
+        field!("ack", h.ack),
+        field!("off", h.data_offset),
+        field!("res", h.reserved),
+        field!("flags", h.flags.names().join("|")),
+        field!("win", h.window),
+        checksum(&h.checksum),
+        field!("urg", h.urgent_ptr),
     );
+    let opts: Vec<String> = h.opts.iter().map(tcp_opt).collect();
+    if opts.is_empty() {
+        println!();
+    } else {
+        println!(" {}", field!("opts", opts.join(",")));
+    }
 }
 
-pub fn udp(h: crate::udp_h, csum: Option<u16>) {
-    let src: u16 = h.src_port.load_le();
-    let dst: u16 = h.dst_port.load_le();
-    let len: u16 = h.len.load_le();
-    let chk: u16 = h.checksum.load_le();
-
-    let chk_field = match csum {
-        Some(expected) if chk == expected => field!("chk", chk),
-        Some(expected) => bad_field!("chk", chk, expected),
-        None => field!("chk", chk),
-    };
+fn tcp_opt(opt: &TcpOpt) -> String {
+    match opt {
+        TcpOpt::Mss { mss } => format!("mss {}", mss),
+        TcpOpt::WindowScale { shift } => format!("ws {}", shift),
+        TcpOpt::SackPermitted => "sackOK".to_string(),
+        TcpOpt::Sack { blocks } => {
+            let blocks: Vec<String> =
+                blocks.iter().map(|(l, r)| format!("{}-{}", l, r)).collect();
+            format!("sack {}", blocks.join(" "))
+        }
+        TcpOpt::Timestamps { value, echo } => format!("ts {} {}", value, echo),
+        TcpOpt::Md5 => "md5".to_string(),
+        TcpOpt::Ao {
+            key_id,
+            rnext_key_id,
+        } => format!("ao key {} rnext {}", key_id, rnext_key_id),
+        TcpOpt::Malformed { option } => {
+            format!("{:?} {}", option, "<malformed>".red())
+        }
+        TcpOpt::Unknown { code, len } => format!("opt{} len {}", code, len),
+        TcpOpt::Truncated => "<malformed>".red().to_string(),
+    }
+}
 
+fn udp(h: &Udp) {
     println!(
         "{} {} {} {}",
         layer!("UDP"),
-        from_to!(src, dst),
-        field!("len", len),
-        chk_field,
+        from_to!(h.src_port, h.dst_port),
+        field!("len", h.len),
+        checksum(&h.checksum),
     )
 }
 
-pub fn echo(h: crate::echo_h) {
-    let id: u16 = h.id.load_le();
-    let seq: u16 = h.seq.load_le();
+fn echo(h: &Echo) {
     println!(
         "{} {} {}",
         layer!("Echo"),
-        field!("id", id),
-        field!("seq", seq),
+        field!("id", h.id),
+        field!("seq", h.seq),
     )
 }
 
-pub fn icmp(h: crate::icmp_h) {
-    let typ: u8 = h.typ.load_le();
-    let code: u8 = h.code.load_le();
-
-    let (typ, code) = match IcmpType::try_from(typ) {
-        Ok(h) => {
+fn icmp(h: &Icmp<IcmpType>) {
+    let (typ, code) = (h.typ.0, h.code);
+    let (typ, code) = match h.typ.known() {
+        Some(h) => {
             let code = match h {
                 IcmpType::DestinationUnreachable => {
                     match IcmpDUCode::try_from(code) {
@@ -1032,25 +1263,25 @@ pub fn icmp(h: crate::icmp_h) {
             };
             (format!("{:?}", h), code)
         }
-        _ => (format!("{}", typ), format!("{}", code)),
+        None => (format!("{}", typ), format!("{}", code)),
     };
 
-    let chk: u16 = h.hdr_checksum.load_le();
     println!(
         "{} {} {} {}",
         layer!("ICMP"),
         field!("type", typ),
         field!("code", code),
-        field!("chk", chk),
+        checksum(&h.checksum),
     );
+    if let Some(e) = &h.echo {
+        echo(e);
+    }
 }
 
-pub fn icmp6(h: crate::icmp_h) {
-    let typ: u8 = h.typ.load_le();
-    let code: u8 = h.code.load_le();
-
-    let (typ, code) = match Icmp6Type::try_from(typ) {
-        Ok(h) => {
+fn icmp6(h: &Icmp<Icmp6Type>) {
+    let (typ, code) = (h.typ.0, h.code);
+    let (typ, code) = match h.typ.known() {
+        Some(h) => {
             let code = match h {
                 Icmp6Type::DestinationUnreachable => {
                     match Icmp6DUCode::try_from(code) {
@@ -1092,138 +1323,277 @@ pub fn icmp6(h: crate::icmp_h) {
             };
             (format!("{:?}", h), code)
         }
-        _ => (format!("{}", typ), format!("{}", code)),
+        None => (format!("{}", typ), format!("{}", code)),
     };
 
-    let chk: u16 = h.hdr_checksum.load_le();
     println!(
         "{} {} {} {}",
         layer!("ICMP6"),
         field!("type", typ),
         field!("code", code),
-        field!("chk", chk),
+        checksum(&h.checksum),
     );
+    if let Some(e) = &h.echo {
+        echo(e);
+    }
 }
 
-pub fn ddm_discovery(h: crate::ddm_discovery_h, frame: &[u8]) {
-    let ver: u8 = h.version.load();
-    let kind: u8 = h.router_kind.load();
-    let len: u8 = h.hostname_len.load();
-    let kind = match DdmRouterKind::try_from(kind) {
-        Ok(k) => format!("{:?}", k),
-        _ => format!("{}", kind),
+fn ddm_discovery(h: &DdmDiscovery) {
+    let kind = match h.kind.known() {
+        Some(k) => format!("{:?}", k),
+        None => format!("{}", h.kind.0),
     };
-
-    let mut flags = Vec::new();
-    if let Some(&true) = h.flags.get(7).as_deref() {
-        flags.push("Solicit");
-    }
-    if let Some(&true) = h.flags.get(6).as_deref() {
-        flags.push("Advertise");
-    }
-    let flags = flags.join("|");
-
-    let host = &frame[4..4 + (len as usize)];
-
     println!(
         "{} {} {} {} {} {}",
         layer!("DDMd"),
-        field!("version", ver),
-        field!("flags", flags),
+        field!("version", h.version),
+        field!("flags", h.flags.join("|")),
         field!("kind", kind),
-        field!("len", len),
-        field!("host", String::from_utf8_lossy(host))
+        field!("len", h.hostname_len),
+        field!("host", h.hostname)
     );
 }
 
-pub fn bfd(h: crate::bfd_h) {
-    let ver: u8 = h.version.load();
-    let diag: u8 = h.diag.load();
-    let status: u8 = h.status.load();
-    let detect_mult: u8 = h.detect_mult.load();
-    let len: u8 = h.len.load();
-    let mine: u32 = h.my_discriminator.load_le();
-    let yours: u32 = h.your_discriminator.load_le();
-    let dtx: u32 = h.desired_min_tx_interval.load_le();
-    let rtx: u32 = h.required_min_tx_interval.load_le();
-    let recho: u32 = h.required_min_echo_rx_interval.load_le();
-
-    let diag = match BfdDiagnostic::try_from(diag) {
-        Ok(BfdDiagnostic::NoDiagnostic) => String::new(),
-        Ok(d) => format!("{:?}", d),
-        _ => format!("{:?}", diag).red().to_string(),
+fn bfd(h: &Bfd) {
+    let diag = match h.diag.known() {
+        Some(BfdDiagnostic::NoDiagnostic) => String::new(),
+        Some(d) => format!("{:?}", d),
+        None => format!("{:?}", h.diag.0).red().to_string(),
     };
 
-    let status = match BfdStatus::try_from(status) {
-        Ok(s) => format!("{:?}", s),
-        _ => format!("{:?}", status).red().to_string(),
+    let status = match h.status.known() {
+        Some(s) => format!("{:?}", s),
+        None => format!("{:?}", h.status.0).red().to_string(),
     };
 
-    let mut flags = Vec::new();
-    if let Some(&true) = h.poll.get(0).as_deref() {
-        flags.push("poll");
-    }
-    if let Some(&true) = h.fin.get(0).as_deref() {
-        flags.push("final");
-    }
-    if let Some(&true) = h.control_plane_independent.get(0).as_deref() {
-        flags.push("cpi");
-    }
-    if let Some(&true) = h.authentication_present.get(0).as_deref() {
-        flags.push("auth");
-    }
-    if let Some(&true) = h.demand.get(0).as_deref() {
-        flags.push("demand");
-    }
-    if let Some(&true) = h.multipoint.get(0).as_deref() {
-        flags.push("mp");
-    }
-    let flags = flags.join("|");
-
     print!(
         "{} {} {}",
         layer!("Bfd"),
-        field!("ver", ver),
+        field!("ver", h.version),
         field!("status", status),
     );
     if !diag.is_empty() {
         print!(" {}", field!("diag", diag));
     }
-    if !flags.is_empty() {
-        print!(" {}", field!("flags", flags));
+    if !h.flags.is_empty() {
+        print!(" {}", field!("flags", h.flags.join("|")));
     }
     println!(
         " {} {} {} {} {} {} {}",
-        field!("dm", detect_mult),
-        field!("len", len),
-        field!("m", mine),
-        field!("y", yours),
-        field!("dtx", dtx),
-        field!("rtx", rtx),
-        field!("rex", recho),
+        field!("dm", h.detect_mult),
+        field!("len", h.len),
+        field!("m", h.my_discriminator),
+        field!("y", h.your_discriminator),
+        field!("dtx", h.desired_min_tx_interval),
+        field!("rtx", h.required_min_tx_interval),
+        field!("rex", h.required_min_echo_rx_interval),
     );
+    if h.flags.contains(&"auth") {
+        bfd_auth(h.auth.as_ref());
+    }
 }
 
-pub fn geneve(h: crate::geneve_h) {
-    let ver: u8 = h.version.load();
-    let olen: u8 = h.opt_len.load();
-    let ctrl: bool = *h.ctrl.get(0).unwrap();
-    let crit: bool = *h.ctrl.get(0).unwrap();
-    let proto: u16 = h.protocol.load_le();
-    let proto = match Ethertype::try_from(proto) {
-        Ok(h) => format!("{:?}", h).green(),
-        _ => format!("0x{:04x}", proto).green(),
+/// Show the authentication section that follows the BFD control header when
+/// the authentication present flag is set.
+fn bfd_auth(auth: Option<&BfdAuth>) {
+    let Some(auth) = auth else {
+        println!("{} {}", layer!(""), "<truncated auth>".red());
+        return;
     };
-    let vni: u32 = h.vni.load_le();
+    let Some(typ) = auth.typ.known() else {
+        println!(
+            "{} {} {}",
+            layer!(""),
+            field!("auth", format!("{}", auth.typ.0).red()),
+            field!("len", auth.len),
+        );
+        return;
+    };
+    print!(
+        "{} {} {}",
+        layer!(""),
+        field!("auth", format!("{:?}", typ)),
+        field!("key", auth.key),
+    );
+    match (&auth.password, auth.seq) {
+        (Some(password), _) => println!(" {}", field!("password", password)),
+        (_, Some(seq)) => println!(" {}", field!("seq", seq)),
+        _ => println!(" {}", "<truncated auth>".red()),
+    }
+}
+
+/// Show a BFD echo packet. Its content is private to the sender.
+fn bfd_echo(len: usize) {
+    println!("{} {}", layer!("BfdE"), field!("len", len));
+}
 
+fn geneve(h: &Geneve) {
     println!(
         "{} {} {} {} {} {} {}",
         layer!("Gnv"),
-        vni.to_string().blue(),
-        field!("ver", ver),
-        field!("olen", olen),
-        field!("ctrl", ctrl),
-        field!("crit", crit),
-        field!("proto", proto),
+        h.vni.to_string().blue(),
+        field!("ver", h.version),
+        field!("olen", h.opt_len),
+        field!("ctrl", h.ctrl),
+        field!("crit", h.crit),
+        field!("proto", ether_type(h.protocol)),
+    );
+    for opt in &h.opts {
+        geneve_opt(opt);
+    }
+    if h.opts_truncated {
+        println!("{} {}", layer!("Opt"), "<truncated option>".red());
+    }
+}
+
+fn geneve_opt(opt: &GeneveOpt) {
+    let class = match opt.class.known() {
+        Some(c) => format!("{:?}", c),
+        None => format!("0x{:04x}", opt.class.0),
+    };
+    let typ = match opt.typ {
+        GeneveOptType::Oxide(t) => match t.known() {
+            Some(t) => format!("{:?}", t),
+            None => format!("0x{:02x}", t.0),
+        },
+        GeneveOptType::Other(t) => format!("0x{:02x}", t),
+    };
+    print!(
+        "{} {} {} {}",
+        layer!("Opt"),
+        field!("class", class.green()),
+        field!("type", typ),
+        field!("crit", opt.crit),
+    );
+    if let Some(repl) = opt.replication {
+        let repl = match repl.known() {
+            Some(r) => format!("{:?}", r),
+            None => format!("{}", repl.0),
+        };
+        println!(" {}", field!("repl", repl));
+    } else if let Some(mss) = opt.mss {
+        println!(" {}", field!("mss", mss));
+    } else if opt.data.is_empty() {
+        println!();
+    } else {
+        let hex: String =
+            opt.data.iter().map(|b| format!("{:02x}", b)).collect();
+        println!(" {}", field!("data", format!("0x{hex}")));
+    }
+}
+
+/// Show the BGP messages in a TCP segment.
+fn bgp(h: &Bgp) {
+    if h.continued > 0 {
+        println!("{} {}", layer!("BGP"), field!("continued", h.continued));
+        return;
+    }
+    for msg in &h.messages {
+        let Some(typ) = msg.typ.known() else {
+            println!("{} {}", layer!("BGP"), field!("type", msg.typ.0));
+            return;
+        };
+        print!("{} {}", layer!("BGP"), format!("{:?}", typ).green());
+        if msg.len < BGP_HEADER_LEN {
+            println!(" {}", bad_field!("len", msg.len, BGP_HEADER_LEN));
+            return;
+        }
+        if let Some(captured) = msg.captured {
+            // The rest of the message is in the next segment.
+            println!(
+                " {} {}",
+                field!("len", msg.len),
+                field!("captured", captured).red()
+            );
+            return;
+        }
+        println!(" {}", field!("len", msg.len));
+        match &msg.body {
+            Some(BgpBody::Open(o)) => bgp_open(o),
+            Some(BgpBody::Update(u)) => bgp_update(u),
+            Some(BgpBody::Notification(n)) => bgp_notification(n),
+            Some(BgpBody::Malformed) => {
+                println!("{} {}", layer!(""), "<malformed message>".red())
+            }
+            None => {}
+        }
+    }
+    if h.trailing > 0 {
+        println!("{} {}", layer!("BGP"), field!("trailing", h.trailing));
+    }
+}
+
+fn bgp_open(h: &BgpOpen) {
+    println!(
+        "{} {} {} {} {}",
+        layer!(""),
+        field!("ver", h.version),
+        field!("as", h.asn.to_string().blue()),
+        field!("hold", h.hold),
+        field!("id", h.id.to_string().blue()),
     );
+    if !h.caps.is_empty() {
+        println!("{} {}", layer!(""), field!("caps", h.caps.join("|")));
+    }
+}
+
+fn bgp_update(h: &BgpUpdate) {
+    if !h.withdrawn.is_empty() {
+        let w = h.withdrawn.join(" ");
+        println!("{} {}", layer!(""), field!("withdrawn", w));
+    }
+    for attr in &h.attrs {
+        let name = match attr.typ.known() {
+            Some(a) => format!("{:?}", a),
+            None => format!("attr{}", attr.typ.0),
+        };
+        println!("{} {}", layer!(""), field!(name, attr.value));
+    }
+    if !h.nlri.is_empty() {
+        println!("{} {}", layer!(""), field!("nlri", h.nlri.join(" ")));
+    }
+}
+
+fn bgp_notification(h: &BgpNotification) {
+    let code = match h.code.known() {
+        Some(c) => format!("{:?}", c).red().to_string(),
+        None => format!("{}", h.code.0),
+    };
+    println!(
+        "{} {} {} {}",
+        layer!(""),
+        field!("code", code),
+        field!("sub", h.subcode),
+        field!("data", h.data_len),
+    );
+}
+
+pub fn stream_event(src: SocketAddr, dst: SocketAddr, what: &str) {
+    println!(
+        "{} {} {}",
+        layer!("Flow"),
+        from_to!(src, dst),
+        what.dimmed()
+    );
+}
+
+/// Show reassembled stream data as text, with unprintable bytes as dots.
+pub fn stream_data(src: SocketAddr, dst: SocketAddr, data: &[u8]) {
+    println!(
+        "{} {} {}",
+        layer!("Flow"),
+        from_to!(src, dst),
+        field!("len", data.len()),
+    );
+    let text: String = String::from_utf8_lossy(data)
+        .replace("\r\n", "\n")
+        .chars()
+        .map(|c| match c {
+            '\n' | '\t' => c,
+            c if c.is_control() || c == char::REPLACEMENT_CHARACTER => '.',
+            c => c,
+        })
+        .collect();
+    println!("{}", text.trim_end_matches('\n'));
+    sep();
 }
@@ -0,0 +1,1710 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Filter expressions and their compilation into ingress table entries.
+//!
+//! An expression is made of comparisons joined with `and`, `or` and `not`
+//! (or `&&`, `||` and `!`) and grouped with parentheses, for example
+//!
+//! ```text
+//! (ip.addr == fd00::1 and tcp.port 179) or bfd
+//! ```
+//!
+//! A comparison is a field name, an optional `==` or `!=` and a value. A bare
+//! protocol name such as `arp`, `tcp` or `bgp` is shorthand for a comparison
+//! against `eth.type`, `ip.proto` or `alp`, in that order. Prefixing a field
+//! or protocol name with `inner.` matches on the encapsulated packet instead.
+//!
+//! Each ingress table can only keep frames matching any of a set of values
+//! and drop frames matching others, and tables are applied one after the
+//! other. The expression is therefore rewritten as alternatives made of
+//! per-table constraints, each alternative gets its own pipeline, and a frame
+//! is kept when any pipeline keeps it.
+//!
+//! The table for a header field is only applied to frames that carry the
+//! header, so a comparison also requires the header to be present, through
+//! the tables matching on the Ethertype, on the network and transport layers
+//! the parser found, or on the application layer protocol. `tcp.port 179`
+//! thus does not keep ARP frames, while `not tcp.port 179` does.
+
+use crate::cli::Filter;
+use crate::dump::{Alp, Ethertype, GeneveOptClass, IpProto, OxideOptType};
+use crate::{headers_t, main_pipeline};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use macaddr::MacAddr6;
+use p4rs::packet_in;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Upper bound on the number of alternatives, and thus pipelines, an
+/// expression may expand to.
+const MAX_ALTERNATIVES: usize = 32;
+
+/// Number of Geneve options the parser extracts for the option table.
+const GENEVE_OPT_SLOTS: usize = 4;
+
+/// Priorities of the table entries. Excluded values take precedence over
+/// kept ones, which take precedence over the catch-all drop that is installed
+/// alongside kept values.
+const PRIO_EXCLUDE: u32 = 200;
+const PRIO_KEEP: u32 = 100;
+const PRIO_DEFAULT: u32 = 0;
+
+/// A compiled filter.
+pub struct Program {
+    /// The pipelines of the alternatives that can match, along with the
+    /// index of the alternative.
+    pipelines: Vec<(usize, main_pipeline)>,
+}
+
+impl Program {
+    /// Compile the filter flags and expression in `cfg`. The flags apply to
+    /// every alternative of the expression.
+    pub fn new(cfg: &Filter) -> Result<Self> {
+        let flags = flag_clauses(cfg);
+        let alternatives = match &cfg.expr {
+            Some(expr) => {
+                let expr = Parser::parse(expr)?;
+                let mut alternatives = expand(&expr, false)?;
+                for a in &mut alternatives {
+                    a.extend(flags.iter().cloned());
+                }
+                alternatives
+            }
+            None => vec![flags],
+        };
+        let mut pipelines = Vec::new();
+        for (i, alternative) in alternatives.iter().enumerate() {
+            if let Some(entries) = compile(alternative)? {
+                pipelines.push((i, install(&entries)));
+            }
+        }
+        if pipelines.is_empty() {
+            return Err(anyhow!("filter cannot match any frame"));
+        }
+        Ok(Self { pipelines })
+    }
+
+    /// Run a frame through the filter, returning the index of the first
+    /// alternative that keeps it along with the parsed headers.
+    pub fn process(
+        &mut self,
+        frame: &[u8],
+    ) -> Option<(usize, Vec<(headers_t, u16)>)> {
+        for (i, pipeline) in &mut self.pipelines {
+            let mut pkt = packet_in::new(frame);
+            let hdrs = pipeline.process_packet_headers(0, &mut pkt);
+            if !hdrs.is_empty() {
+                return Some((*i, hdrs));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transport {
+    Any,
+    Tcp,
+    Udp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    EthSrc,
+    EthDst,
+    EthAddr,
+    EthType,
+    VlanId,
+    IpSrc,
+    IpDst,
+    IpAddr,
+    IpProto,
+    SrcPort(Transport),
+    DstPort(Transport),
+    Port(Transport),
+    Vni,
+    GeneveOpt,
+    Alp,
+    /// The Ethertype of the network layer header, only used for the headers
+    /// that other fields require.
+    L3Type,
+    /// The IP protocol of the transport layer header, as for `L3Type`.
+    L4Proto,
+}
+
+/// Field names, the first name listed for a field is used in messages.
+const FIELDS: &[(&str, Field)] = &[
+    ("eth.src", Field::EthSrc),
+    ("eth.dst", Field::EthDst),
+    ("eth.addr", Field::EthAddr),
+    ("eth.host", Field::EthAddr),
+    ("eth.type", Field::EthType),
+    ("vlan.id", Field::VlanId),
+    ("ip.src", Field::IpSrc),
+    ("ip.dst", Field::IpDst),
+    ("ip.addr", Field::IpAddr),
+    ("ip.host", Field::IpAddr),
+    ("ip.proto", Field::IpProto),
+    ("srcport", Field::SrcPort(Transport::Any)),
+    ("dstport", Field::DstPort(Transport::Any)),
+    ("port", Field::Port(Transport::Any)),
+    ("tcp.srcport", Field::SrcPort(Transport::Tcp)),
+    ("tcp.dstport", Field::DstPort(Transport::Tcp)),
+    ("tcp.port", Field::Port(Transport::Tcp)),
+    ("udp.srcport", Field::SrcPort(Transport::Udp)),
+    ("udp.dstport", Field::DstPort(Transport::Udp)),
+    ("udp.port", Field::Port(Transport::Udp)),
+    ("geneve.vni", Field::Vni),
+    ("geneve.opt", Field::GeneveOpt),
+    ("alp", Field::Alp),
+];
+
+impl Field {
+    fn lookup(name: &str) -> Option<Self> {
+        FIELDS.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::L3Type => "network layer",
+            Self::L4Proto => "transport layer",
+            _ => FIELDS
+                .iter()
+                .find(|(_, f)| f == self)
+                .map(|(n, _)| *n)
+                .unwrap(),
+        }
+    }
+
+    /// Whether the field can be matched on encapsulated packets.
+    fn has_inner(&self) -> bool {
+        !matches!(self, Self::VlanId | Self::Vni | Self::GeneveOpt)
+    }
+
+    fn value(&self, s: &str) -> Result<Value> {
+        let value = match self {
+            Self::EthSrc | Self::EthDst | Self::EthAddr => {
+                s.parse().ok().map(Value::Mac)
+            }
+            Self::EthType => match Ethertype::from_str(s, true) {
+                Ok(t) => Some(Value::Int(t as u16)),
+                Err(_) => number(s, 0xffff),
+            },
+            Self::VlanId => number(s, 0xfff),
+            Self::IpSrc | Self::IpDst | Self::IpAddr => {
+                s.parse().ok().map(Value::Prefix)
+            }
+            Self::IpProto => match IpProto::from_str(s, true) {
+                Ok(p) => Some(Value::Int(p as u16)),
+                Err(_) => number(s, 0xff),
+            },
+            Self::SrcPort(_) | Self::DstPort(_) | Self::Port(_) => {
+                s.parse().ok().map(Value::Ports)
+            }
+            Self::Vni => s.parse().ok().map(Value::Vnis),
+            Self::GeneveOpt => s.parse().ok().map(Value::GeneveOpt),
+            Self::Alp => match Alp::from_str(s, true) {
+                Ok(a) => Some(Value::Int(a as u16)),
+                Err(_) => number(s, 0xff),
+            },
+            Self::L3Type | Self::L4Proto => None,
+        };
+        value.ok_or_else(|| anyhow!("invalid value `{s}` for {}", self.name()))
+    }
+}
+
+fn number(s: &str, max: u64) -> Option<Value> {
+    integer(s, max).map(|n| Value::Int(n as u16))
+}
+
+/// Parse a decimal or `0x` prefixed hexadecimal integer no larger than `max`.
+fn integer(s: &str, max: u64) -> Option<u64> {
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => s.parse().ok()?,
+    };
+    (n <= max).then_some(n)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Value {
+    Int(u16),
+    Prefix(IpPrefix),
+    Ports(PortRange),
+    Vnis(VniRange),
+    GeneveOpt(GeneveOpt),
+    Mac(MacMatch),
+}
+
+/// A set of MAC addresses: a single address, a prefix such as an OUI written
+/// as `a8:40:25:00:00:00/24`, or the `broadcast` and `multicast` keywords.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MacMatch {
+    Prefix { addr: u64, len: u8 },
+    Multicast,
+}
+
+impl MacMatch {
+    /// Key matching the addresses in the set.
+    fn key(&self) -> Vec<u8> {
+        let (addr, mask) = match *self {
+            Self::Prefix { addr, len } => {
+                (addr, !((1u64 << (48 - len)) - 1) & 0xffff_ffff_ffff)
+            }
+            // The group bit is the least significant bit of the first octet.
+            Self::Multicast => (1 << 40, 1 << 40),
+        };
+        masked(&addr.to_le_bytes()[..6], &mask.to_le_bytes()[..6])
+    }
+}
+
+impl FromStr for MacMatch {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "broadcast" => {
+                return Ok(Self::Prefix {
+                    addr: 0xffff_ffff_ffff,
+                    len: 48,
+                })
+            }
+            "multicast" => return Ok(Self::Multicast),
+            _ => {}
+        }
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, len.parse()?),
+            None => (s, 48),
+        };
+        if len > 48 {
+            return Err(anyhow!("prefix length {len} is longer than 48"));
+        }
+        let addr = addr
+            .parse::<MacAddr6>()?
+            .as_bytes()
+            .iter()
+            .fold(0u64, |acc, b| acc << 8 | u64::from(*b));
+        Ok(Self::Prefix { addr, len })
+    }
+}
+
+/// An inclusive range of transport ports, written as `first-last` or as a
+/// single port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortRange {
+    first: u16,
+    last: u16,
+}
+
+impl PortRange {
+    fn bounds(&self) -> (Vec<u8>, Vec<u8>) {
+        (
+            self.first.to_le_bytes().to_vec(),
+            self.last.to_le_bytes().to_vec(),
+        )
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (first.parse()?, last.parse()?),
+            None => {
+                let port = s.parse()?;
+                (port, port)
+            }
+        };
+        if first > last {
+            return Err(anyhow!("port range {s} is empty"));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// An inclusive range of Geneve VNIs, written as `first-last` or as a single
+/// VNI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VniRange {
+    first: u32,
+    last: u32,
+}
+
+impl VniRange {
+    const MAX: u32 = 0xff_ffff;
+
+    fn bounds(&self) -> (Vec<u8>, Vec<u8>) {
+        (
+            self.first.to_le_bytes()[..3].to_vec(),
+            self.last.to_le_bytes()[..3].to_vec(),
+        )
+    }
+}
+
+impl FromStr for VniRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (first.parse()?, last.parse()?),
+            None => {
+                let vni = s.parse()?;
+                (vni, vni)
+            }
+        };
+        if last > Self::MAX {
+            return Err(anyhow!("VNI {last} does not fit in 24 bits"));
+        }
+        if first > last {
+            return Err(anyhow!("VNI range {s} is empty"));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// A Geneve option, written as `class:type` with an optional `:value` that
+/// must equal the first word of the option data. The class and type are
+/// numbers, or names for the Oxide option class, e.g. `oxide:external`. Any of
+/// the first [`GENEVE_OPT_SLOTS`] options of a frame can match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GeneveOpt {
+    class: u16,
+    typ: u8,
+    value: Option<u32>,
+}
+
+impl GeneveOpt {
+    /// Keys matching the option in any slot, leaving the other slots
+    /// unspecified.
+    fn keys(&self) -> Vec<Vec<u8>> {
+        let key = self.key();
+        let any = vec![0; key.len()];
+        (0..GENEVE_OPT_SLOTS)
+            .map(|slot| {
+                let mut keys = vec![&any; GENEVE_OPT_SLOTS];
+                keys[slot] = &key;
+                keys.into_iter().flatten().copied().collect()
+            })
+            .collect()
+    }
+
+    /// Key for a single slot.
+    fn key(&self) -> Vec<u8> {
+        let value = match self.value {
+            Some(v) => ternary(&v.to_le_bytes()),
+            None => vec![0; 9],
+        };
+        [
+            ternary(&self.class.to_le_bytes()),
+            ternary(&[self.typ]),
+            value,
+        ]
+        .concat()
+    }
+}
+
+impl FromStr for GeneveOpt {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let (Some(class), Some(typ)) = (parts.next(), parts.next()) else {
+            return Err(anyhow!("expected class:type[:value]"));
+        };
+        let value = parts.next();
+        if parts.next().is_some() {
+            return Err(anyhow!("expected class:type[:value]"));
+        }
+
+        let class = match GeneveOptClass::from_str(class, true) {
+            Ok(c) => c as u16,
+            Err(_) => integer(class, 0xffff)
+                .ok_or_else(|| anyhow!("invalid option class `{class}`"))?
+                as u16,
+        };
+        let oxide = class == GeneveOptClass::Oxide as u16;
+        let typ = match OxideOptType::from_str(typ, true) {
+            Ok(t) if oxide => t as u8,
+            _ => integer(typ, 0x7f)
+                .ok_or_else(|| anyhow!("invalid option type `{typ}`"))?
+                as u8,
+        };
+        let value = value
+            .map(|v| {
+                integer(v, u32::MAX.into())
+                    .ok_or_else(|| anyhow!("invalid option value `{v}`"))
+            })
+            .transpose()?
+            .map(|v| v as u32);
+        Ok(Self { class, typ, value })
+    }
+}
+
+/// An IPv4 or IPv6 prefix in CIDR notation. An address without a prefix
+/// length matches only itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpPrefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl IpPrefix {
+    /// Key matching the addresses covered by the prefix.
+    fn key(&self) -> Vec<u8> {
+        let len = u32::from(self.len);
+        match self.addr {
+            IpAddr::V4(a) => {
+                let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+                masked(&u32::from(a).to_le_bytes(), &mask.to_le_bytes())
+            }
+            IpAddr::V6(a) => {
+                let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+                masked(&u128::from(a).to_le_bytes(), &mask.to_le_bytes())
+            }
+        }
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse()?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let len = match len {
+            Some(len) => len.parse()?,
+            None => max,
+        };
+        if len > max {
+            return Err(anyhow!("prefix length {len} is longer than {max}"));
+        }
+        Ok(Self { addr, len })
+    }
+}
+
+/// A single comparison of a field against a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Pred {
+    inner: bool,
+    field: Field,
+    value: Value,
+}
+
+impl Pred {
+    /// Resolve a bare protocol name.
+    fn shorthand(inner: bool, name: &str) -> Option<Self> {
+        let (field, value) = if let Ok(t) = Ethertype::from_str(name, true) {
+            (Field::EthType, t as u16)
+        } else if let Ok(p) = IpProto::from_str(name, true) {
+            (Field::IpProto, p as u16)
+        } else if let Ok(a) = Alp::from_str(name, true) {
+            (Field::Alp, a as u16)
+        } else {
+            return None;
+        };
+        Some(Self {
+            inner,
+            field,
+            value: Value::Int(value),
+        })
+    }
+
+    /// The table entries that match this comparison, as table and key pairs.
+    fn entries(&self) -> Vec<(Table, Vec<u8>)> {
+        match (self.field, self.value) {
+            (Field::EthSrc, Value::Mac(m)) => {
+                vec![(Table::Eth(AddrTable::Src), m.key())]
+            }
+            (Field::EthDst, Value::Mac(m)) => {
+                vec![(Table::Eth(AddrTable::Dst), m.key())]
+            }
+            (Field::EthAddr, Value::Mac(m)) => {
+                let table = Table::Eth(AddrTable::Host);
+                let keys = either(m.key(), table.wildcard());
+                keys.map(|key| (table, key)).to_vec()
+            }
+            (Field::IpSrc, Value::Prefix(p)) => {
+                vec![(Table::ip(p, AddrTable::Src), p.key())]
+            }
+            (Field::IpDst, Value::Prefix(p)) => {
+                vec![(Table::ip(p, AddrTable::Dst), p.key())]
+            }
+            (Field::IpAddr, Value::Prefix(p)) => {
+                let table = Table::ip(p, AddrTable::Host);
+                let keys = either(p.key(), table.wildcard());
+                keys.map(|key| (table, key)).to_vec()
+            }
+            (Field::EthType, Value::Int(v)) => {
+                vec![(Table::EthType, ternary(&v.to_le_bytes()))]
+            }
+            (Field::VlanId, Value::Int(v)) => {
+                vec![(Table::VlanVid, ternary(&v.to_le_bytes()))]
+            }
+            (Field::IpProto, Value::Int(v)) => {
+                let key = ternary(&[v as u8]);
+                vec![(Table::Ipv4Proto, key.clone()), (Table::Ipv6Proto, key)]
+            }
+            (Field::SrcPort(t), Value::Ports(p)) => {
+                vec![(Table::SrcPort(t), range(p.bounds()))]
+            }
+            (Field::DstPort(t), Value::Ports(p)) => {
+                vec![(Table::DstPort(t), range(p.bounds()))]
+            }
+            (Field::Port(t), Value::Ports(p)) => {
+                let table = Table::Port(t);
+                let keys = either(range(p.bounds()), table.wildcard());
+                keys.map(|key| (table, key)).to_vec()
+            }
+            (Field::Vni, Value::Vnis(v)) => {
+                vec![(Table::Vni, range(v.bounds()))]
+            }
+            (Field::GeneveOpt, Value::GeneveOpt(o)) => o
+                .keys()
+                .into_iter()
+                .map(|key| (Table::GeneveOpt, key))
+                .collect(),
+            (Field::Alp, Value::Int(v)) => {
+                vec![(Table::Alp, ternary(&[v as u8]))]
+            }
+            (Field::L3Type, Value::Int(v)) => {
+                vec![(Table::L3Type, ternary(&v.to_le_bytes()))]
+            }
+            (Field::L4Proto, Value::Int(v)) => {
+                vec![(Table::L4Proto, ternary(&[v as u8]))]
+            }
+            // Field::value only produces addresses for the address fields.
+            _ => unreachable!(),
+        }
+    }
+
+    /// The values a field must have for the header this comparison refers to
+    /// to be present, as a field of a table that is applied whether or not
+    /// the header is. `None` if the comparison is on such a table already.
+    fn requires(&self) -> Option<(bool, Field, Vec<u16>)> {
+        use Transport::{Any, Tcp, Udp};
+        let (v4, v6) = (Ethertype::IPv4 as u16, Ethertype::IPv6 as u16);
+        let (tcp, udp) = (IpProto::Tcp as u16, IpProto::Udp as u16);
+        let geneve = (false, Field::Alp, vec![Alp::Geneve as u16]);
+        let required = match (self.field, self.value) {
+            // The outer Ethernet header is always there, the inner one is
+            // only parsed after a Geneve header.
+            (
+                Field::EthSrc | Field::EthDst | Field::EthAddr | Field::EthType,
+                _,
+            ) => {
+                if !self.inner {
+                    return None;
+                }
+                geneve
+            }
+            (Field::VlanId, _) => {
+                (false, Field::EthType, vec![Ethertype::Vlan as u16])
+            }
+            (Field::IpSrc | Field::IpDst | Field::IpAddr, Value::Prefix(p)) => {
+                let t = if p.addr.is_ipv4() { v4 } else { v6 };
+                (self.inner, Field::L3Type, vec![t])
+            }
+            (Field::IpProto, _) => (self.inner, Field::L3Type, vec![v4, v6]),
+            (Field::SrcPort(t) | Field::DstPort(t) | Field::Port(t), _) => {
+                let protos = match t {
+                    Any => vec![tcp, udp],
+                    Tcp => vec![tcp],
+                    Udp => vec![udp],
+                };
+                (self.inner, Field::L4Proto, protos)
+            }
+            (Field::Vni | Field::GeneveOpt, _) => geneve,
+            (Field::Alp | Field::L3Type | Field::L4Proto, _) => return None,
+            // Field::value only produces prefixes for the address fields.
+            (Field::IpSrc | Field::IpDst | Field::IpAddr, _) => unreachable!(),
+        };
+        Some(required)
+    }
+}
+
+/// Key for a single ternary field matching `value` exactly.
+fn ternary(value: &[u8]) -> Vec<u8> {
+    masked(value, &vec![0xff; value.len()])
+}
+
+/// Key for a single ternary field matching the bits of `value` that are set
+/// in `mask`: a care byte followed by the masked value and the mask.
+fn masked(value: &[u8], mask: &[u8]) -> Vec<u8> {
+    let mut key = vec![1];
+    key.extend(value.iter().zip(mask).map(|(v, m)| v & m));
+    key.extend_from_slice(mask);
+    key
+}
+
+/// Key for a single range field.
+fn range((first, last): (Vec<u8>, Vec<u8>)) -> Vec<u8> {
+    [first, last].concat()
+}
+
+/// Keys for a table with two fields of the same kind, matching `key` in
+/// either field. The `wildcard` key matches anything in both fields.
+fn either(key: Vec<u8>, wildcard: Vec<u8>) -> [Vec<u8>; 2] {
+    let other = &wildcard[key.len()..];
+    [[&key, other].concat(), [other, &key].concat()]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AddrTable {
+    Src,
+    Dst,
+    Host,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Table {
+    Eth(AddrTable),
+    EthType,
+    VlanVid,
+    Ipv4(AddrTable),
+    Ipv6(AddrTable),
+    Ipv4Proto,
+    Ipv6Proto,
+    SrcPort(Transport),
+    DstPort(Transport),
+    Port(Transport),
+    Vni,
+    GeneveOpt,
+    Alp,
+    L3Type,
+    L4Proto,
+}
+
+impl Table {
+    fn ip(p: IpPrefix, t: AddrTable) -> Self {
+        match p.addr {
+            IpAddr::V4(_) => Self::Ipv4(t),
+            IpAddr::V6(_) => Self::Ipv6(t),
+        }
+    }
+
+    /// A key that matches any value. Ternary fields are wildcarded by clearing
+    /// their care byte and mask, and range fields by covering every value.
+    fn wildcard(&self) -> Vec<u8> {
+        let ternary = |width: usize| vec![0; 1 + 2 * width];
+        let range = |width: usize| [vec![0; width], vec![0xff; width]].concat();
+        match self {
+            Self::Eth(AddrTable::Host) => ternary(6).repeat(2),
+            Self::Eth(_) => ternary(6),
+            Self::EthType | Self::VlanVid | Self::L3Type => ternary(2),
+            Self::Ipv4(AddrTable::Host) => ternary(4).repeat(2),
+            Self::Ipv4(_) => ternary(4),
+            Self::Ipv6(AddrTable::Host) => ternary(16).repeat(2),
+            Self::Ipv6(_) => ternary(16),
+            Self::Ipv4Proto | Self::Ipv6Proto | Self::Alp | Self::L4Proto => {
+                ternary(1)
+            }
+            Self::SrcPort(_) | Self::DstPort(_) => range(2),
+            Self::Port(_) => range(2).repeat(2),
+            Self::Vni => range(3),
+            Self::GeneveOpt => [ternary(2), ternary(1), ternary(4)]
+                .concat()
+                .repeat(GENEVE_OPT_SLOTS),
+        }
+    }
+}
+
+/// A table entry, in the outer or inner instance of a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    inner: bool,
+    table: Table,
+    action: &'static str,
+    key: Vec<u8>,
+    priority: u32,
+}
+
+/// Add an entry to a pipeline. The parser only produces inner comparisons
+/// for the fields that have inner tables.
+fn add_entry(pipeline: &mut main_pipeline, entry: &Entry) {
+    let Entry {
+        inner,
+        table,
+        action,
+        ref key,
+        priority,
+    } = *entry;
+    macro_rules! add {
+        ($outer:ident, $inner:ident) => {
+            if inner {
+                pipeline.$inner(action, key, &[], priority)
+            } else {
+                pipeline.$outer(action, key, &[], priority)
+            }
+        };
+    }
+    use Transport::{Any, Tcp, Udp};
+    match table {
+        Table::Eth(AddrTable::Src) => {
+            add!(add_ingress_eth_src_entry, add_ingress_inner_eth_src_entry)
+        }
+        Table::Eth(AddrTable::Dst) => {
+            add!(add_ingress_eth_dst_entry, add_ingress_inner_eth_dst_entry)
+        }
+        Table::Eth(AddrTable::Host) => {
+            add!(add_ingress_eth_host_entry, add_ingress_inner_eth_host_entry)
+        }
+        Table::EthType => add!(
+            add_ingress_eth_ethertype_entry,
+            add_ingress_inner_eth_ethertype_entry
+        ),
+        Table::VlanVid => {
+            pipeline.add_ingress_vlan_vid_entry(action, key, &[], priority)
+        }
+        Table::Ipv4(AddrTable::Src) => {
+            add!(add_ingress_ipv4_src_entry, add_ingress_inner_ipv4_src_entry)
+        }
+        Table::Ipv4(AddrTable::Dst) => {
+            add!(add_ingress_ipv4_dst_entry, add_ingress_inner_ipv4_dst_entry)
+        }
+        Table::Ipv4(AddrTable::Host) => add!(
+            add_ingress_ipv4_host_entry,
+            add_ingress_inner_ipv4_host_entry
+        ),
+        Table::Ipv6(AddrTable::Src) => {
+            add!(add_ingress_ipv6_src_entry, add_ingress_inner_ipv6_src_entry)
+        }
+        Table::Ipv6(AddrTable::Dst) => {
+            add!(add_ingress_ipv6_dst_entry, add_ingress_inner_ipv6_dst_entry)
+        }
+        Table::Ipv6(AddrTable::Host) => add!(
+            add_ingress_ipv6_host_entry,
+            add_ingress_inner_ipv6_host_entry
+        ),
+        Table::Ipv4Proto => add!(
+            add_ingress_ipv4_proto_entry,
+            add_ingress_inner_ipv4_proto_entry
+        ),
+        Table::Ipv6Proto => add!(
+            add_ingress_ipv6_proto_entry,
+            add_ingress_inner_ipv6_proto_entry
+        ),
+        Table::SrcPort(Any) => add!(
+            add_ingress_ports_src_entry,
+            add_ingress_inner_ports_src_entry
+        ),
+        Table::DstPort(Any) => add!(
+            add_ingress_ports_dst_entry,
+            add_ingress_inner_ports_dst_entry
+        ),
+        Table::Port(Any) => add!(
+            add_ingress_ports_port_entry,
+            add_ingress_inner_ports_port_entry
+        ),
+        Table::SrcPort(Tcp) => add!(
+            add_ingress_tcp_ports_src_entry,
+            add_ingress_inner_tcp_ports_src_entry
+        ),
+        Table::DstPort(Tcp) => add!(
+            add_ingress_tcp_ports_dst_entry,
+            add_ingress_inner_tcp_ports_dst_entry
+        ),
+        Table::Port(Tcp) => add!(
+            add_ingress_tcp_ports_port_entry,
+            add_ingress_inner_tcp_ports_port_entry
+        ),
+        Table::SrcPort(Udp) => add!(
+            add_ingress_udp_ports_src_entry,
+            add_ingress_inner_udp_ports_src_entry
+        ),
+        Table::DstPort(Udp) => add!(
+            add_ingress_udp_ports_dst_entry,
+            add_ingress_inner_udp_ports_dst_entry
+        ),
+        Table::Port(Udp) => add!(
+            add_ingress_udp_ports_port_entry,
+            add_ingress_inner_udp_ports_port_entry
+        ),
+        Table::Vni => {
+            pipeline.add_ingress_geneve_vni_entry(action, key, &[], priority)
+        }
+        Table::GeneveOpt => {
+            pipeline.add_ingress_geneve_opt_entry(action, key, &[], priority)
+        }
+        Table::Alp => add!(
+            add_ingress_app_proto_entry,
+            add_ingress_inner_app_proto_entry
+        ),
+        Table::L3Type => add!(
+            add_ingress_layers_l3_entry,
+            add_ingress_inner_layers_l3_entry
+        ),
+        Table::L4Proto => add!(
+            add_ingress_layers_l4_entry,
+            add_ingress_inner_layers_l4_entry
+        ),
+    }
+}
+
+/// A constraint on a single field that can be expressed with the entries of
+/// the tables for that field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Clause {
+    /// The field matches any of the comparisons.
+    Any(Vec<Pred>),
+    /// The field does not match the comparison.
+    Not(Pred),
+}
+
+/// A conjunction of clauses, compiled into a single pipeline.
+type Alternative = Vec<Clause>;
+
+/// The table entries for an alternative, or `None` if no frame can match it.
+fn compile(alternative: &Alternative) -> Result<Option<Vec<Entry>>> {
+    // The values kept for each field, including the values required for the
+    // headers that the comparisons refer to to be present.
+    let mut kept: Vec<Vec<Pred>> = Vec::new();
+    let mut excluded: Vec<Pred> = Vec::new();
+    for clause in alternative {
+        match clause {
+            Clause::Any(preds) => {
+                let sets = [required(preds), Some(preds.clone())];
+                for preds in sets.into_iter().flatten() {
+                    if !keep(&mut kept, preds)? {
+                        return Ok(None);
+                    }
+                }
+            }
+            Clause::Not(p) => {
+                if !excluded.contains(p) {
+                    excluded.push(*p);
+                }
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    for preds in &kept {
+        let inner = preds[0].inner;
+        let mut tables: Vec<Table> = Vec::new();
+        for p in preds {
+            for (table, key) in p.entries() {
+                entries.push(Entry {
+                    inner,
+                    table,
+                    action: "keep",
+                    key,
+                    priority: PRIO_KEEP,
+                });
+                if !tables.contains(&table) {
+                    tables.push(table);
+                }
+            }
+        }
+        for table in tables {
+            entries.push(Entry {
+                inner,
+                table,
+                action: "drop",
+                key: table.wildcard(),
+                priority: PRIO_DEFAULT,
+            });
+        }
+    }
+    for p in &excluded {
+        for (table, key) in p.entries() {
+            entries.push(Entry {
+                inner: p.inner,
+                table,
+                action: "drop",
+                key,
+                priority: PRIO_EXCLUDE,
+            });
+        }
+    }
+    Ok(Some(entries))
+}
+
+/// The comparisons a frame must also match for the headers that `preds`
+/// refer to to be present, if any. The comparisons of a clause are all on the
+/// same field, so they require the same field of another table.
+fn required(preds: &[Pred]) -> Option<Vec<Pred>> {
+    let mut required: Vec<Pred> = Vec::new();
+    for p in preds {
+        let (inner, field, values) = p.requires()?;
+        for value in values {
+            let r = Pred {
+                inner,
+                field,
+                value: Value::Int(value),
+            };
+            if !required.contains(&r) {
+                required.push(r);
+            }
+        }
+    }
+    Some(required)
+}
+
+/// Merge the values of a clause into those already kept for its field. A
+/// table can only keep one set of values, so integer values are intersected
+/// and other sets must be the same. Returns whether any value is left.
+fn keep(kept: &mut Vec<Vec<Pred>>, preds: Vec<Pred>) -> Result<bool> {
+    let (inner, field) = (preds[0].inner, preds[0].field);
+    let Some(current) = kept
+        .iter_mut()
+        .find(|k| k[0].inner == inner && k[0].field == field)
+    else {
+        kept.push(preds);
+        return Ok(true);
+    };
+    if *current == preds {
+        return Ok(true);
+    }
+    let int = |p: &Pred| matches!(p.value, Value::Int(_));
+    if !current.iter().chain(&preds).all(int) {
+        return Err(anyhow!(
+            "filter cannot be represented: {}{} cannot be required to match \
+            more than one set of values at once",
+            if inner { "inner." } else { "" },
+            field.name(),
+        ));
+    }
+    current.retain(|p| preds.contains(p));
+    Ok(!current.is_empty())
+}
+
+/// Build a pipeline holding `entries`.
+fn install(entries: &[Entry]) -> main_pipeline {
+    let mut pipeline = main_pipeline::new(2);
+    for entry in entries {
+        add_entry(&mut pipeline, entry);
+    }
+    pipeline
+}
+
+/// Clauses for the filter flags. Values given for the same field are
+/// alternatives, different fields must all match.
+fn flag_clauses(cfg: &Filter) -> Alternative {
+    let eth = |t: &Ethertype| Value::Int(*t as u16);
+    let prefix = |p: &IpPrefix| Value::Prefix(*p);
+    let proto = |p: &IpProto| Value::Int(*p as u16);
+    let int = |v: &u16| Value::Int(*v);
+    let ports = |p: &PortRange| Value::Ports(*p);
+    let vnis = |v: &VniRange| Value::Vnis(*v);
+    let opt = |o: &GeneveOpt| Value::GeneveOpt(*o);
+    let mac = |m: &MacMatch| Value::Mac(*m);
+    let alp = |a: &Alp| Value::Int(*a as u16);
+    let t = Transport::Any;
+    let mut c = Vec::new();
+
+    let mut eth_types: Vec<Ethertype> = cfg.eth_type.into_iter().collect();
+    for (set, t) in [
+        (cfg.vlan, Ethertype::Vlan),
+        (cfg.v4, Ethertype::IPv4),
+        (cfg.v6, Ethertype::IPv6),
+        (cfg.arp, Ethertype::Arp),
+    ] {
+        if set {
+            eth_types.push(t);
+        }
+    }
+    any(&mut c, false, Field::EthType, eth_types.iter().map(eth));
+    any(&mut c, false, Field::EthSrc, cfg.eth_src.iter().map(mac));
+    any(&mut c, false, Field::EthDst, cfg.eth_dst.iter().map(mac));
+    any(&mut c, false, Field::EthAddr, cfg.eth_host.iter().map(mac));
+    any(&mut c, false, Field::IpSrc, cfg.ip_src.iter().map(prefix));
+    any(&mut c, false, Field::IpDst, cfg.ip_dst.iter().map(prefix));
+    any(&mut c, false, Field::IpAddr, cfg.ip_host.iter().map(prefix));
+    any(
+        &mut c,
+        false,
+        Field::IpProto,
+        cfg.ip_proto.iter().map(proto),
+    );
+    any(
+        &mut c,
+        false,
+        Field::SrcPort(t),
+        cfg.src_port.iter().map(ports),
+    );
+    any(
+        &mut c,
+        false,
+        Field::DstPort(t),
+        cfg.dst_port.iter().map(ports),
+    );
+    any(&mut c, false, Field::Port(t), cfg.port.iter().map(ports));
+    any(&mut c, false, Field::Vni, cfg.vni.iter().map(vnis));
+    any(
+        &mut c,
+        false,
+        Field::GeneveOpt,
+        cfg.geneve_opt.iter().map(opt),
+    );
+    any(&mut c, false, Field::Alp, cfg.alp.iter().map(alp));
+    any(&mut c, false, Field::VlanId, cfg.vid.iter().map(int));
+
+    let mut eth_types = cfg.inner_eth_type.clone();
+    for (set, t) in [
+        (cfg.inner_v4, Ethertype::IPv4),
+        (cfg.inner_v6, Ethertype::IPv6),
+        (cfg.inner_arp, Ethertype::Arp),
+    ] {
+        if set {
+            eth_types.push(t);
+        }
+    }
+    any(&mut c, true, Field::EthType, eth_types.iter().map(eth));
+    any(
+        &mut c,
+        true,
+        Field::EthSrc,
+        cfg.inner_eth_src.iter().map(mac),
+    );
+    any(
+        &mut c,
+        true,
+        Field::EthDst,
+        cfg.inner_eth_dst.iter().map(mac),
+    );
+    any(
+        &mut c,
+        true,
+        Field::EthAddr,
+        cfg.inner_eth_host.iter().map(mac),
+    );
+    any(
+        &mut c,
+        true,
+        Field::IpSrc,
+        cfg.inner_ip_src.iter().map(prefix),
+    );
+    any(
+        &mut c,
+        true,
+        Field::IpDst,
+        cfg.inner_ip_dst.iter().map(prefix),
+    );
+    any(
+        &mut c,
+        true,
+        Field::IpAddr,
+        cfg.inner_ip_host.iter().map(prefix),
+    );
+    any(
+        &mut c,
+        true,
+        Field::IpProto,
+        cfg.inner_ip_proto.iter().map(proto),
+    );
+    any(
+        &mut c,
+        true,
+        Field::SrcPort(t),
+        cfg.inner_src_port.iter().map(ports),
+    );
+    any(
+        &mut c,
+        true,
+        Field::DstPort(t),
+        cfg.inner_dst_port.iter().map(ports),
+    );
+    any(
+        &mut c,
+        true,
+        Field::Port(t),
+        cfg.inner_port.iter().map(ports),
+    );
+    any(&mut c, true, Field::Alp, cfg.inner_alp.iter().map(alp));
+
+    c
+}
+
+/// Add a clause matching any of `values`, unless there are none.
+fn any(
+    clauses: &mut Alternative,
+    inner: bool,
+    field: Field,
+    values: impl Iterator<Item = Value>,
+) {
+    let preds: Vec<Pred> = values
+        .map(|value| Pred {
+            inner,
+            field,
+            value,
+        })
+        .collect();
+    if !preds.is_empty() {
+        clauses.push(Clause::Any(preds));
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    Pred(Pred),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// Rewrite an expression, negated if `negate` is set, as a disjunction of
+/// alternatives. Alternatives of comparisons on the same field are kept
+/// together as a single clause rather than expanded.
+fn expand(expr: &Expr, negate: bool) -> Result<Vec<Alternative>> {
+    let (conjunction, a, b) = match expr {
+        Expr::Pred(p) => {
+            let clause = if negate {
+                Clause::Not(*p)
+            } else {
+                Clause::Any(vec![*p])
+            };
+            return Ok(vec![vec![clause]]);
+        }
+        Expr::Not(e) => return expand(e, !negate),
+        Expr::And(a, b) => (!negate, a, b),
+        Expr::Or(a, b) => (negate, a, b),
+    };
+    let a = expand(a, negate)?;
+    let b = expand(b, negate)?;
+
+    let result = if conjunction {
+        let mut result = Vec::new();
+        for x in &a {
+            for y in &b {
+                result.push(x.iter().chain(y).cloned().collect());
+            }
+        }
+        result
+    } else {
+        match (a.as_slice(), b.as_slice()) {
+            ([x], [y]) => match (x.as_slice(), y.as_slice()) {
+                ([Clause::Any(p)], [Clause::Any(q)])
+                    if p[0].inner == q[0].inner && p[0].field == q[0].field =>
+                {
+                    let preds = p.iter().chain(q).copied().collect();
+                    vec![vec![Clause::Any(preds)]]
+                }
+                _ => vec![x.clone(), y.clone()],
+            },
+            _ => a.into_iter().chain(b).collect(),
+        }
+    };
+    if result.len() > MAX_ALTERNATIVES {
+        return Err(anyhow!(
+            "filter expands to more than {MAX_ALTERNATIVES} alternatives"
+        ));
+    }
+    Ok(result)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Eq,
+    Ne,
+    Word(String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Ne,
+            '!' => Token::Not,
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Eq
+            }
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '&' | '|' => return Err(anyhow!("expected `{c}{c}`")),
+            c => {
+                let mut word = String::from(c);
+                while let Some(c) = chars
+                    .next_if(|c| !c.is_whitespace() && !"()!=&|".contains(*c))
+                {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse(s: &str) -> Result<Expr> {
+        let mut p = Self {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = p.or()?;
+        match p.next() {
+            None => Ok(expr),
+            Some(t) => Err(anyhow!("unexpected {} in filter", describe(&t))),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn accept(&mut self, t: &Token) -> bool {
+        if self.tokens.get(self.pos) == Some(t) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut e = self.and()?;
+        while self.accept(&Token::Or) {
+            e = Expr::Or(Box::new(e), Box::new(self.and()?));
+        }
+        Ok(e)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut e = self.unary()?;
+        while self.accept(&Token::And) {
+            e = Expr::And(Box::new(e), Box::new(self.unary()?));
+        }
+        Ok(e)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let e = self.or()?;
+                if !self.accept(&Token::RParen) {
+                    return Err(anyhow!("missing `)` in filter"));
+                }
+                Ok(e)
+            }
+            Some(Token::Word(w)) => self.comparison(&w),
+            Some(t) => Err(anyhow!("unexpected {} in filter", describe(&t))),
+            None => Err(anyhow!("unexpected end of filter")),
+        }
+    }
+
+    fn comparison(&mut self, name: &str) -> Result<Expr> {
+        let (inner, base) = match name.strip_prefix("inner.") {
+            Some(base) => (true, base),
+            None => (false, name),
+        };
+        let Some(field) = Field::lookup(base) else {
+            return Pred::shorthand(inner, base)
+                .map(Expr::Pred)
+                .ok_or_else(|| anyhow!("unknown field or protocol `{name}`"));
+        };
+        if inner && !field.has_inner() {
+            return Err(anyhow!(
+                "filter cannot be represented: {base} cannot be matched on \
+                encapsulated packets"
+            ));
+        }
+        let negate = self.accept(&Token::Ne);
+        if !negate {
+            self.accept(&Token::Eq);
+        }
+        let value = match self.next() {
+            Some(Token::Word(v)) => field.value(&v)?,
+            _ => return Err(anyhow!("expected a value after `{name}`")),
+        };
+        let pred = Expr::Pred(Pred {
+            inner,
+            field,
+            value,
+        });
+        Ok(if negate {
+            Expr::Not(Box::new(pred))
+        } else {
+            pred
+        })
+    }
+}
+
+fn describe(t: &Token) -> String {
+    match t {
+        Token::LParen => "`(`".into(),
+        Token::RParen => "`)`".into(),
+        Token::Not => "`not`".into(),
+        Token::And => "`and`".into(),
+        Token::Or => "`or`".into(),
+        Token::Eq => "`==`".into(),
+        Token::Ne => "`!=`".into(),
+        Token::Word(w) => format!("`{w}`"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pred(inner: bool, field: Field, value: Value) -> Pred {
+        Pred {
+            inner,
+            field,
+            value,
+        }
+    }
+
+    fn tcp_port(port: u16) -> Pred {
+        let ports = PortRange {
+            first: port,
+            last: port,
+        };
+        pred(false, Field::Port(Transport::Tcp), Value::Ports(ports))
+    }
+
+    fn alp(a: Alp) -> Pred {
+        pred(false, Field::Alp, Value::Int(a as u16))
+    }
+
+    fn alternatives(s: &str) -> Vec<Alternative> {
+        expand(&Parser::parse(s).unwrap(), false).unwrap()
+    }
+
+    fn entries(s: &str) -> Option<Vec<Entry>> {
+        let alternatives = alternatives(s);
+        assert_eq!(alternatives.len(), 1);
+        compile(&alternatives[0]).unwrap()
+    }
+
+    fn entry(table: Table, action: &'static str, key: Vec<u8>) -> Entry {
+        let priority = match (action, key == table.wildcard()) {
+            ("keep", _) => PRIO_KEEP,
+            (_, true) => PRIO_DEFAULT,
+            _ => PRIO_EXCLUDE,
+        };
+        Entry {
+            inner: false,
+            table,
+            action,
+            key,
+            priority,
+        }
+    }
+
+    #[test]
+    fn tokenize_words_and_operators() {
+        use Token::*;
+        let word = |w: &str| Word(w.into());
+        assert_eq!(
+            tokenize("(ip.src==10.0.0.1&&!tcp.port 22) || bfd").unwrap(),
+            [
+                LParen,
+                word("ip.src"),
+                Eq,
+                word("10.0.0.1"),
+                And,
+                Not,
+                word("tcp.port"),
+                word("22"),
+                RParen,
+                Or,
+                word("bfd"),
+            ]
+        );
+        assert_eq!(
+            tokenize("not alp != bgp and  port = 1-2 or x").unwrap(),
+            [
+                Not,
+                word("alp"),
+                Ne,
+                word("bgp"),
+                And,
+                word("port"),
+                Eq,
+                word("1-2"),
+                Or,
+                word("x"),
+            ]
+        );
+        assert!(tokenize("tcp & udp").is_err());
+        assert!(tokenize("tcp | udp").is_err());
+    }
+
+    #[test]
+    fn parse_precedence() {
+        let p = |s| Expr::Pred(Pred::shorthand(false, s).unwrap());
+        let b = Box::new;
+        assert_eq!(
+            Parser::parse("tcp or udp and not bfd").unwrap(),
+            Expr::Or(
+                b(p("tcp")),
+                b(Expr::And(b(p("udp")), b(Expr::Not(b(p("bfd"))))))
+            )
+        );
+        assert_eq!(
+            Parser::parse("(tcp or udp) and bfd").unwrap(),
+            Expr::And(b(Expr::Or(b(p("tcp")), b(p("udp")))), b(p("bfd")))
+        );
+        assert_eq!(
+            Parser::parse("tcp.port != 22").unwrap(),
+            Expr::Not(b(Expr::Pred(tcp_port(22))))
+        );
+        assert_eq!(
+            Parser::parse("tcp.port == 22").unwrap(),
+            Parser::parse("tcp.port 22").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_comparisons() {
+        let Expr::Pred(p) = Parser::parse("inner.ip.src == fd00::/8").unwrap()
+        else {
+            panic!("expected a comparison");
+        };
+        assert!(p.inner);
+        assert_eq!(p.field, Field::IpSrc);
+        assert_eq!(p.value, Value::Prefix("fd00::/8".parse().unwrap()));
+
+        // Ethertypes are looked up before IP protocols and protocols.
+        assert_eq!(
+            Parser::parse("ipv4").unwrap(),
+            Expr::Pred(pred(
+                false,
+                Field::EthType,
+                Value::Int(Ethertype::IPv4 as u16)
+            ))
+        );
+        assert_eq!(Parser::parse("bgp").unwrap(), Expr::Pred(alp(Alp::Bgp)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for s in [
+            "",
+            "tcp.port",
+            "(tcp",
+            "tcp)",
+            "tcp udp",
+            "tcp and",
+            "nosuch",
+            "ip.src == 300.0.0.1",
+            "vlan.id 4096",
+            "inner.vlan.id 5",
+            "inner.geneve.vni 5",
+        ] {
+            assert!(Parser::parse(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn expand_distributes() {
+        let (bfd, bgp) = (alp(Alp::Bfd), alp(Alp::Bgp));
+        assert_eq!(
+            alternatives("(tcp.port 1 or bfd) and not tcp.port 2"),
+            [
+                vec![Clause::Any(vec![tcp_port(1)]), Clause::Not(tcp_port(2))],
+                vec![Clause::Any(vec![bfd]), Clause::Not(tcp_port(2))],
+            ]
+        );
+        // De Morgan.
+        assert_eq!(
+            alternatives("not (bfd or bgp)"),
+            [vec![Clause::Not(bfd), Clause::Not(bgp)]]
+        );
+        assert_eq!(
+            alternatives("not (bfd and bgp)"),
+            [vec![Clause::Not(bfd)], vec![Clause::Not(bgp)]]
+        );
+    }
+
+    #[test]
+    fn expand_merges_same_field() {
+        assert_eq!(
+            alternatives("tcp.port 1 or tcp.port 2"),
+            [vec![Clause::Any(vec![tcp_port(1), tcp_port(2)])]]
+        );
+        // Unless one of them is on the encapsulated packet.
+        assert_eq!(alternatives("bfd or inner.bfd").len(), 2);
+    }
+
+    #[test]
+    fn expand_limits_alternatives() {
+        let clause = "(tcp.port 1 or bfd)";
+        let five = [clause; 5].join(" and ");
+        assert_eq!(alternatives(&five).len(), MAX_ALTERNATIVES);
+        let six = [clause; 6].join(" and ");
+        assert!(expand(&Parser::parse(&six).unwrap(), false).is_err());
+    }
+
+    #[test]
+    fn compile_requires_headers() {
+        let tcp = ternary(&[IpProto::Tcp as u8]);
+        let table = Table::Port(Transport::Tcp);
+        let [a, b] =
+            either(range((vec![179, 0], vec![179, 0])), table.wildcard());
+        assert_eq!(
+            entries("tcp.port 179").unwrap(),
+            [
+                entry(Table::L4Proto, "keep", tcp),
+                entry(Table::L4Proto, "drop", Table::L4Proto.wildcard()),
+                entry(table, "keep", a),
+                entry(table, "keep", b),
+                entry(table, "drop", table.wildcard()),
+            ]
+        );
+
+        // Application layer protocols are matched whatever the headers.
+        let bfd = ternary(&[Alp::Bfd as u8]);
+        assert_eq!(
+            entries("bfd").unwrap(),
+            [
+                entry(Table::Alp, "keep", bfd),
+                entry(Table::Alp, "drop", Table::Alp.wildcard()),
+            ]
+        );
+
+        // Negated comparisons keep frames without the header.
+        let key = range((vec![22, 0], vec![22, 0]));
+        let [a, b] = either(key, table.wildcard());
+        let v4 = ternary(&(Ethertype::IPv4 as u16).to_le_bytes());
+        let src = Table::Ipv4(AddrTable::Src);
+        let addr = ternary(&[1, 0, 0, 10]);
+        assert_eq!(
+            entries("ip.src == 10.0.0.1 and not tcp.port 22").unwrap(),
+            [
+                entry(Table::L3Type, "keep", v4),
+                entry(Table::L3Type, "drop", Table::L3Type.wildcard()),
+                entry(src, "keep", addr),
+                entry(src, "drop", src.wildcard()),
+                entry(table, "drop", a),
+                entry(table, "drop", b),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_inner_requires_geneve() {
+        let entries = entries("inner.eth.type ipv6").unwrap();
+        let geneve = ternary(&[Alp::Geneve as u8]);
+        assert!(entries.contains(&entry(Table::Alp, "keep", geneve)));
+        assert!(entries.iter().any(|e| e.inner && e.table == Table::EthType));
+    }
+
+    #[test]
+    fn compile_geneve_opt_any_slot() {
+        let opt: GeneveOpt = "oxide:external".parse().unwrap();
+        let slot = opt.key();
+        let kept: Vec<_> = entries("geneve.opt oxide:external")
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.table == Table::GeneveOpt && e.action == "keep")
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(kept.len(), GENEVE_OPT_SLOTS);
+        for (i, key) in kept.iter().enumerate() {
+            for (j, k) in key.chunks(slot.len()).enumerate() {
+                if i == j {
+                    assert_eq!(k, slot);
+                } else {
+                    assert!(k.iter().all(|b| *b == 0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ip_prefix_keys() {
+        let key = |s: &str| s.parse::<IpPrefix>().unwrap().key();
+        // Host bits are cleared, the mask follows the value.
+        assert_eq!(key("10.1.2.3/8"), [1, 0, 0, 0, 10, 0, 0, 0, 0xff].to_vec());
+        assert_eq!(key("10.0.0.1"), ternary(&[1, 0, 0, 10]));
+        assert_eq!(key("0.0.0.0/0"), masked(&[0; 4], &[0; 4]));
+
+        let v6 = key("fd00:1122:3344::1/48");
+        let (value, mask) = v6[1..].split_at(16);
+        let mut expected = [0u8; 16];
+        expected[10..].copy_from_slice(&[0x44, 0x33, 0x22, 0x11, 0x00, 0xfd]);
+        assert_eq!(value, expected);
+        assert_eq!(mask, [&[0; 10][..], &[0xff; 6]].concat());
+        assert_eq!(v6.len(), Table::Ipv6(AddrTable::Src).wildcard().len());
+    }
+
+    #[test]
+    fn mac_keys() {
+        let key = |s: &str| s.parse::<MacMatch>().unwrap().key();
+        assert_eq!(
+            key("multicast"),
+            masked(&[0, 0, 0, 0, 0, 1], &[0, 0, 0, 0, 0, 1])
+        );
+        assert_eq!(key("broadcast"), ternary(&[0xff; 6]));
+        assert_eq!(
+            key("a8:40:25:12:34:56/24"),
+            masked(&[0, 0, 0, 0x25, 0x40, 0xa8], &[0, 0, 0, 0xff, 0xff, 0xff])
+        );
+
+        // One entry per address field of the host table.
+        let table = Table::Eth(AddrTable::Host);
+        let kept = entries("eth.addr multicast")
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.table == table && e.action == "keep")
+            .count();
+        assert_eq!(kept, 2);
+    }
+
+    #[test]
+    fn compile_intersects_requirements() {
+        // Both ports require TCP or UDP, the protocol narrows it to UDP.
+        let kept = entries("port 53 and udp.port 53").unwrap();
+        let l4: Vec<_> = kept
+            .iter()
+            .filter(|e| e.table == Table::L4Proto && e.action == "keep")
+            .collect();
+        assert_eq!(l4.len(), 1);
+        assert_eq!(l4[0].key, ternary(&[IpProto::Udp as u8]));
+
+        // A comparison that can never hold drops the alternative.
+        assert_eq!(entries("geneve.vni 5 and bgp"), None);
+        assert_eq!(entries("ip.src 10.0.0.1 and ip.src fd00::1"), None);
+        assert_eq!(entries("vlan.id 5 and eth.type ipv4"), None);
+    }
+
+    #[test]
+    fn compile_rejects_unrepresentable() {
+        let alternatives = alternatives("tcp.port 1 and tcp.port 2");
+        assert!(compile(&alternatives[0]).is_err());
+
+        // The same set twice is fine.
+        assert!(entries("tcp.port 1 and tcp.port 1").is_some());
+    }
+
+    #[test]
+    fn program_needs_a_possible_alternative() {
+        let cfg = |expr: &str| Filter {
+            expr: Some(expr.into()),
+            ..Default::default()
+        };
+        assert!(Program::new(&cfg("bgp and bfd")).is_err());
+        assert!(Program::new(&cfg("(bgp and bfd) or bgp")).is_ok());
+        assert!(Program::new(&Filter::default()).is_ok());
+    }
+}
@@ -0,0 +1,450 @@
+// Copyright 2026 Oxide Computer Company
+
+//! TCP stream reassembly.
+//!
+//! Each direction of a connection is tracked separately and keyed on its
+//! addresses and ports, using the encapsulated packet for Geneve frames.
+//! Payload is handed out in sequence order once it is contiguous, with
+//! retransmitted data dropped and out of order segments held back until the
+//! gap before them is filled.
+//!
+//! Directions that see no segment for a while, by capture time, are
+//! forgotten, as is the least recently active one when too many are tracked,
+//! so that connections that never close do not hold memory and open files.
+
+use crate::cli::Follow;
+use crate::decode::{DecodedPacket, TcpFlags, L4};
+use crate::dump;
+use anyhow::Result;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Out of order data held for a direction before giving up on the gap in
+/// front of it.
+const MAX_PENDING: usize = 4 << 20;
+
+/// Directions tracked at once, each of which may hold a file open.
+const MAX_STREAMS: usize = 512;
+
+/// Time after which a direction that saw no segment is forgotten.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often idle directions are looked for.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One direction of a TCP connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Flow {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+}
+
+impl Flow {
+    fn reverse(&self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+        }
+    }
+
+    /// A file name for the direction, e.g. 10.0.0.1.80-10.0.0.2.1234.
+    fn file_name(&self) -> String {
+        format!(
+            "{}.{}-{}.{}",
+            self.src.ip(),
+            self.src.port(),
+            self.dst.ip(),
+            self.dst.port()
+        )
+    }
+}
+
+/// A TCP segment taken from a frame.
+struct Segment<'a> {
+    flow: Flow,
+    seq: u32,
+    syn: bool,
+    fin: bool,
+    rst: bool,
+    payload: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    fn new(p: &DecodedPacket<'a>) -> Option<Self> {
+        let (l4, v4, v6) = if p.inner_l4.is_some() {
+            (&p.inner_l4, &p.inner_ipv4, &p.inner_ipv6)
+        } else {
+            (&p.l4, &p.ipv4, &p.ipv6)
+        };
+        let Some(L4::Tcp(tcp)) = l4 else {
+            return None;
+        };
+        let (src, dst): (IpAddr, IpAddr) = match (v4, v6) {
+            (Some(ip), _) => (ip.src.into(), ip.dst.into()),
+            (_, Some(ip)) => (ip.src.into(), ip.dst.into()),
+            _ => return None,
+        };
+        Some(Self {
+            flow: Flow {
+                src: SocketAddr::new(src, tcp.src_port),
+                dst: SocketAddr::new(dst, tcp.dst_port),
+            },
+            seq: tcp.seq,
+            syn: tcp.flags.has(TcpFlags::SYN),
+            fin: tcp.flags.has(TcpFlags::FIN),
+            rst: tcp.flags.has(TcpFlags::RST),
+            payload: tcp.payload,
+        })
+    }
+}
+
+/// Reassembly state for one direction of a connection.
+struct Stream {
+    /// Capture time of the last segment.
+    last: SystemTime,
+    /// Sequence number of the next byte to hand out, once known.
+    next: Option<u32>,
+    /// Sequence number of the FIN, once seen.
+    fin: Option<u32>,
+    /// Segments that arrived ahead of `next`.
+    pending: Vec<(u32, Vec<u8>)>,
+    /// Where the payload is written, if following to files.
+    file: Option<File>,
+}
+
+impl Stream {
+    fn new(file: Option<File>, time: SystemTime) -> Self {
+        Self {
+            last: time,
+            next: None,
+            fin: None,
+            pending: Vec::new(),
+            file,
+        }
+    }
+
+    /// Accept a segment. Its payload is held until [`Stream::drain`] finds it
+    /// contiguous.
+    fn push(&mut self, seg: &Segment) {
+        let mut seq = seg.seq;
+        if seg.syn {
+            // The SYN takes up one sequence number.
+            seq = seq.wrapping_add(1);
+        }
+        // A stream starts at its SYN, or at the first segment seen when joined
+        // part way through. A retransmitted or stray SYN does not move it.
+        self.next.get_or_insert(seq);
+        if seg.fin {
+            self.fin = Some(seq.wrapping_add(seg.payload.len() as u32));
+        }
+        if !seg.payload.is_empty() {
+            self.pending.push((seq, seg.payload.to_vec()));
+        }
+    }
+
+    /// Take the data that is contiguous with what was handed out so far.
+    fn drain(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let Some(mut next) = self.next else {
+            return out;
+        };
+        loop {
+            // Drop whatever is entirely behind `next`, i.e. retransmitted.
+            self.pending.retain(|(seq, data)| {
+                distance(next, seq.wrapping_add(data.len() as u32)) > 0
+            });
+            let Some(i) = self
+                .pending
+                .iter()
+                .position(|(seq, _)| distance(next, *seq) <= 0)
+            else {
+                break;
+            };
+            let (seq, data) = self.pending.swap_remove(i);
+            let skip = distance(seq, next) as usize;
+            out.extend_from_slice(&data[skip..]);
+            next = seq.wrapping_add(data.len() as u32);
+            self.next = Some(next);
+        }
+        out
+    }
+
+    /// If too much data is waiting on a gap, skip ahead to the closest pending
+    /// segment. Returns the size of the gap skipped.
+    fn skip_gap(&mut self) -> Option<u32> {
+        let held: usize = self.pending.iter().map(|(_, d)| d.len()).sum();
+        if held <= MAX_PENDING {
+            return None;
+        }
+        let next = self.next?;
+        let seq = self
+            .pending
+            .iter()
+            .map(|(seq, _)| *seq)
+            .min_by_key(|seq| distance(next, *seq))?;
+        self.next = Some(seq);
+        Some(seq.wrapping_sub(next))
+    }
+
+    /// Write out or show the data that became contiguous.
+    fn emit(&mut self, flow: &Flow) -> Result<()> {
+        let data = self.drain();
+        if data.is_empty() {
+            return Ok(());
+        }
+        match &mut self.file {
+            Some(f) => f.write_all(&data)?,
+            None => dump::stream_data(flow.src, flow.dst, &data),
+        }
+        Ok(())
+    }
+
+    fn closed(&self) -> bool {
+        self.fin.is_some() && self.fin == self.next
+    }
+}
+
+/// Time elapsed from `earlier` to `later`, zero if the capture went back in
+/// time.
+fn elapsed(earlier: SystemTime, later: SystemTime) -> Duration {
+    later.duration_since(earlier).unwrap_or_default()
+}
+
+/// Signed distance from sequence number `a` to `b`, accounting for wrap.
+fn distance(a: u32, b: u32) -> i32 {
+    b.wrapping_sub(a) as i32
+}
+
+/// Reassembles TCP streams and shows, or writes out, their payload.
+pub struct Follower {
+    streams: HashMap<Flow, Stream>,
+    /// Capture time of the last look for idle directions.
+    expired: Option<SystemTime>,
+    /// Directory to write each direction's payload to. When not set the
+    /// payload is printed.
+    dir: Option<PathBuf>,
+}
+
+impl Follower {
+    /// Create a follower if following was asked for.
+    pub fn new(args: &Follow) -> Result<Option<Self>> {
+        if !args.enabled {
+            return Ok(None);
+        }
+        if let Some(dir) = &args.dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(Some(Self {
+            streams: HashMap::new(),
+            expired: None,
+            dir: args.dir.clone(),
+        }))
+    }
+
+    /// Feed a frame that passed the filters, captured at `time`. Frames that
+    /// do not carry TCP are ignored.
+    pub fn frame(&mut self, p: &DecodedPacket, time: SystemTime) -> Result<()> {
+        self.expire(time);
+        let Some(seg) = Segment::new(p) else {
+            return Ok(());
+        };
+        let flow = seg.flow;
+        if seg.rst {
+            let fwd = self.streams.remove(&flow);
+            let rev = self.streams.remove(&flow.reverse());
+            if fwd.is_some() || rev.is_some() {
+                dump::stream_event(flow.src, flow.dst, "reset");
+            }
+            return Ok(());
+        }
+        // Bare acknowledgements, e.g. the last one of a connection, do not
+        // start a stream.
+        if !self.streams.contains_key(&flow) {
+            if !seg.syn && seg.payload.is_empty() {
+                return Ok(());
+            }
+            if self.streams.len() >= MAX_STREAMS {
+                self.evict_oldest();
+            }
+        }
+        let stream = match self.streams.entry(flow) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                dump::stream_event(flow.src, flow.dst, "opened");
+                let file = match &self.dir {
+                    Some(dir) => Some(
+                        File::options()
+                            .create(true)
+                            .append(true)
+                            .open(dir.join(flow.file_name()))?,
+                    ),
+                    None => None,
+                };
+                e.insert(Stream::new(file, time))
+            }
+        };
+        stream.last = time;
+
+        stream.push(&seg);
+        stream.emit(&flow)?;
+        if let Some(gap) = stream.skip_gap() {
+            let what = format!("{} missing bytes skipped", gap);
+            dump::stream_event(flow.src, flow.dst, &what);
+            stream.emit(&flow)?;
+        }
+        if stream.closed() {
+            dump::stream_event(flow.src, flow.dst, "closed");
+            self.streams.remove(&flow);
+        }
+        Ok(())
+    }
+
+    /// Forget the directions that saw no segment for [`IDLE_TIMEOUT`].
+    fn expire(&mut self, now: SystemTime) {
+        if self
+            .expired
+            .is_some_and(|t| elapsed(t, now) < EXPIRE_INTERVAL)
+        {
+            return;
+        }
+        self.expired = Some(now);
+        let mut idle: Vec<(SystemTime, Flow)> = self
+            .streams
+            .iter()
+            .filter(|(_, s)| elapsed(s.last, now) >= IDLE_TIMEOUT)
+            .map(|(flow, s)| (s.last, *flow))
+            .collect();
+        idle.sort_by_key(|(last, _)| *last);
+        for (_, flow) in idle {
+            self.forget(&flow, "expired");
+        }
+    }
+
+    /// Forget the least recently active direction to make room for another.
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .streams
+            .iter()
+            .min_by_key(|(_, s)| s.last)
+            .map(|(flow, _)| *flow);
+        if let Some(flow) = oldest {
+            self.forget(&flow, "evicted");
+        }
+    }
+
+    /// Stop tracking a direction, closing its file. Data still held back by a
+    /// gap is lost.
+    fn forget(&mut self, flow: &Flow, why: &str) {
+        if self.streams.remove(flow).is_some() {
+            dump::stream_event(flow.src, flow.dst, why);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn segment(seq: u32, syn: bool, payload: &[u8]) -> Segment<'_> {
+        Segment {
+            flow: Flow {
+                src: "10.0.0.1:1234".parse().unwrap(),
+                dst: "10.0.0.2:80".parse().unwrap(),
+            },
+            seq,
+            syn,
+            fin: false,
+            rst: false,
+            payload,
+        }
+    }
+
+    #[test]
+    fn reorders_and_drops_retransmits() {
+        let mut s = Stream::new(None, UNIX_EPOCH);
+        s.push(&segment(99, true, b""));
+        s.push(&segment(105, false, b"world"));
+        assert_eq!(s.drain(), b"");
+        s.push(&segment(100, false, b"hello"));
+        assert_eq!(s.drain(), b"helloworld");
+        s.push(&segment(103, false, b"loworld!"));
+        assert_eq!(s.drain(), b"!");
+    }
+
+    #[test]
+    fn syn_does_not_move_stream() {
+        let mut s = Stream::new(None, UNIX_EPOCH);
+        s.push(&segment(99, true, b""));
+        s.push(&segment(100, false, b"hello"));
+        assert_eq!(s.drain(), b"hello");
+        // A retransmitted SYN, then a stray one.
+        s.push(&segment(99, true, b""));
+        s.push(&segment(5000, true, b""));
+        s.push(&segment(105, false, b"world"));
+        assert_eq!(s.drain(), b"world");
+        assert_eq!(s.next, Some(110));
+    }
+
+    #[test]
+    fn joined_part_way() {
+        let mut s = Stream::new(None, UNIX_EPOCH);
+        s.push(&segment(1000, false, b"data"));
+        assert_eq!(s.drain(), b"data");
+        s.push(&segment(99, true, b""));
+        assert_eq!(s.next, Some(1004));
+    }
+
+    fn flow(port: u16) -> Flow {
+        Flow {
+            src: SocketAddr::from(([10, 0, 0, 1], port)),
+            dst: "10.0.0.2:80".parse().unwrap(),
+        }
+    }
+
+    fn follower(streams: &[(u16, u64)]) -> Follower {
+        let streams = streams
+            .iter()
+            .map(|(port, secs)| {
+                let time = UNIX_EPOCH + Duration::from_secs(*secs);
+                (flow(*port), Stream::new(None, time))
+            })
+            .collect();
+        Follower {
+            streams,
+            expired: None,
+            dir: None,
+        }
+    }
+
+    #[test]
+    fn expires_idle_streams() {
+        let mut f = follower(&[(1, 0), (2, 100)]);
+        let t = |ms| UNIX_EPOCH + Duration::from_millis(ms);
+        f.expire(t(299_900));
+        assert_eq!(f.streams.len(), 2);
+        // Looked for at most once per interval.
+        f.expire(t(300_500));
+        assert_eq!(f.streams.len(), 2);
+        f.expire(t(301_000));
+        assert!(!f.streams.contains_key(&flow(1)));
+        assert!(f.streams.contains_key(&flow(2)));
+        // A capture going back in time expires nothing.
+        f.expire(t(0));
+        f.expire(t(1_000));
+        assert!(f.streams.contains_key(&flow(2)));
+    }
+
+    #[test]
+    fn evicts_least_recently_active() {
+        let mut f = follower(&[(1, 20), (2, 10), (3, 30)]);
+        f.evict_oldest();
+        assert!(!f.streams.contains_key(&flow(2)));
+        assert_eq!(f.streams.len(), 2);
+    }
+}
@@ -1,49 +1,73 @@
 // Copyright 2023 Oxide Computer Company
 
 use anyhow::Result;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Lines};
+use std::time::SystemTime;
 
-use crate::dump;
+use crate::cli::HexRead;
+use crate::follow::Follower;
+use crate::source::{self, Frame, Output, PacketSource, Stats};
 
-pub fn run(filename: &str) -> Result<()> {
-    dump::sep();
-    let hdrs = parse(filename)?;
-    for (h, f) in hdrs {
-        dump::headers(h, &f);
-    }
-    Ok(())
+pub fn run(hr: &HexRead) -> Result<()> {
+    let mut reader = HexReader::new(BufReader::new(source::open(&hr.file)?));
+    let out = Output {
+        hex: false,
+        writer: None,
+        follow: Follower::new(&hr.follow)?,
+        format: hr.output,
+        time: None,
+    };
+    source::run(&mut reader, &hr.filter, &hr.stop, out)
+}
+
+/// Reads hex encoded frames, one or more lines per frame with frames
+/// separated by blank lines.
+pub struct HexReader<R: BufRead> {
+    lines: Lines<R>,
+    received: u64,
 }
 
-pub fn parse(filename: &str) -> Result<Vec<(crate::headers_t, Vec<u8>)>> {
-    let file = File::open(filename)?;
-    let lines = BufReader::new(file).lines();
+impl<R: BufRead> HexReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            lines: input.lines(),
+            received: 0,
+        }
+    }
+}
 
-    let mut result = Vec::new();
-    let mut frame: Vec<u8> = Vec::new();
-    for line in lines {
-        let mut line = line?;
-        line.retain(|c| !c.is_whitespace());
-        if line.is_empty() {
-            let hdr = parse_frame(frame.as_mut_slice());
-            result.push((hdr, frame.clone()));
-            frame = Vec::new();
-        } else {
+impl<R: BufRead> PacketSource for HexReader<R> {
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        let mut frame: Vec<u8> = Vec::new();
+        for line in self.lines.by_ref() {
+            let mut line = line?;
+            line.retain(|c| !c.is_whitespace());
+            if line.is_empty() {
+                if frame.is_empty() {
+                    continue;
+                }
+                break;
+            }
             let data = hex::decode(&line)?;
             frame.extend_from_slice(&data);
         }
+        if frame.is_empty() {
+            return Ok(None);
+        }
+        self.received += 1;
+        // Hex dumps carry no capture metadata.
+        Ok(Some(Frame {
+            orig_len: frame.len(),
+            data: frame,
+            timestamp: SystemTime::now(),
+        }))
     }
-    if !frame.is_empty() {
-        let hdr = parse_frame(frame.as_mut_slice());
-        result.push((hdr, frame.clone()));
-    }
-    Ok(result)
-}
 
-fn parse_frame(f: &mut [u8]) -> crate::headers_t {
-    let mut pkt = p4rs::packet_in::new(f);
-    let mut hdr = crate::headers_t::default();
-    let mut md = crate::ingress_metadata_t::default();
-    crate::parse_start(&mut pkt, &mut hdr, &mut md);
-    hdr
+    fn stats(&mut self) -> Stats {
+        Stats {
+            received: self.received,
+            dropped: None,
+            errors: 0,
+        }
+    }
 }
@@ -0,0 +1,84 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Structured output, one JSON object per frame.
+//!
+//! Each object carries the capture metadata and one member per layer of the
+//! [`DecodedPacket`]. Numeric header fields are numbers, and protocol
+//! constants are strings holding the name used by the text output, or the
+//! value in hex when it has no name.
+
+use crate::decode::DecodedPacket;
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A frame and the metadata it was captured with.
+#[derive(Serialize)]
+pub struct Packet<'p, 'a> {
+    pub timestamp: Timestamp,
+    /// Captured length.
+    pub len: usize,
+    /// Length on the wire.
+    pub orig_len: usize,
+    pub verdict: Verdict,
+    #[serde(flatten)]
+    pub layers: &'p DecodedPacket<'a>,
+    /// The frame in hex, when asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+/// Capture time since the Unix epoch, split into whole seconds and
+/// nanoseconds so that no precision is lost.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Timestamp {
+    pub sec: u64,
+    pub nsec: u32,
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(t: SystemTime) -> Self {
+        t.duration_since(UNIX_EPOCH)
+            .map(|d| Self {
+                sec: d.as_secs(),
+                nsec: d.subsec_nanos(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Why the frame was shown.
+#[derive(Serialize)]
+pub struct Verdict {
+    /// The alternative of the filter expression that kept the frame.
+    pub alternative: usize,
+    /// Whether any checksum in the frame is wrong.
+    pub bad_checksum: bool,
+}
+
+/// Write `p` as a JSON line.
+pub fn frame(
+    out: &mut impl Write,
+    p: &DecodedPacket,
+    timestamp: SystemTime,
+    orig_len: usize,
+    alternative: usize,
+    dump_hex: bool,
+) -> Result<()> {
+    let packet = Packet {
+        timestamp: timestamp.into(),
+        len: p.frame.len(),
+        orig_len,
+        verdict: Verdict {
+            alternative,
+            bad_checksum: p.checksums.is_bad(),
+        },
+        layers: p,
+        data: dump_hex
+            .then(|| p.frame.iter().map(|b| format!("{:02x}", b)).collect()),
+    };
+    serde_json::to_writer(&mut *out, &packet)?;
+    writeln!(out)?;
+    Ok(())
+}
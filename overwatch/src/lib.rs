@@ -0,0 +1,34 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Overwatch as a library.
+//!
+//! Frames come from a [`source::PacketSource`] such as a live
+//! [`link::Link`], a [`pcap::CaptureReader`] or a [`hex_read::HexReader`].
+//! A [`pipeline::Pipeline`] runs them through the P4 filter program and
+//! decodes the ones it keeps into [`decode::DecodedPacket`]s, which can be
+//! rendered as text with [`dump::frame`], as JSON with [`json::frame`] or
+//! reassembled with a [`follow::Follower`]. [`source::run`] ties these
+//! together the way the command line tool does.
+
+pub mod checksum;
+pub mod cli;
+pub mod decode;
+pub mod dump;
+pub mod filter;
+pub mod follow;
+pub mod hex_read;
+pub mod json;
+pub mod link;
+pub mod pcap;
+pub mod pcap_read;
+pub mod pipeline;
+pub mod snoop;
+pub mod source;
+
+/// The headers and pipeline generated from the P4 program, whose controls
+/// take an argument per header they use.
+#[allow(clippy::too_many_arguments)]
+mod p4 {
+    p4_macro::use_p4!(p4 = "p4/overwatch.p4", pipeline_name = "overwatch");
+}
+pub use p4::*;
@@ -1,13 +1,105 @@
 // Copyright 2023 Oxide Computer Company
 
+//! Live capture backends. DLPI is used on illumos and an AF_PACKET ring on
+//! Linux.
+
+use crate::source::{self, Frame, PacketSource, Stats};
 use anyhow::Result;
-use dlpi::DlpiHandle;
-
-pub fn open(name: &str) -> Result<DlpiHandle> {
-    let p = dlpi::open(name, dlpi::sys::DLPI_RAW)?;
-    dlpi::bind(p, 0x86dd)?; //XXX ?
-    dlpi::promisc_on(p, dlpi::sys::DL_PROMISC_MULTI)?;
-    dlpi::promisc_on(p, dlpi::sys::DL_PROMISC_SAP)?;
-    dlpi::promisc_on(p, dlpi::sys::DL_PROMISC_PHYS)?;
-    Ok(p)
+use std::time::{Instant, SystemTime};
+
+#[cfg(target_os = "illumos")]
+mod illumos;
+#[cfg(target_os = "illumos")]
+use illumos as imp;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+use linux as imp;
+
+#[cfg(not(any(target_os = "illumos", target_os = "linux")))]
+mod unsupported;
+#[cfg(not(any(target_os = "illumos", target_os = "linux")))]
+use unsupported as imp;
+
+//TODO figure out from OS MTU
+pub const MAX_FRAME: usize = 9000;
+
+/// Metadata for a received frame.
+struct RecvInfo {
+    /// Number of bytes copied into the receive buffer.
+    len: usize,
+    /// Length of the frame on the wire.
+    orig_len: usize,
+    timestamp: SystemTime,
+}
+
+/// A data link opened for promiscuous capture. Frames longer than
+/// [`MAX_FRAME`] are truncated.
+pub struct Link {
+    imp: imp::Link,
+    buf: Vec<u8>,
+    received: u64,
+    errors: u64,
+    deadline: Option<Instant>,
+}
+
+impl Link {
+    pub fn open(name: &str) -> Result<Self> {
+        Ok(Self {
+            imp: imp::Link::open(name)?,
+            buf: vec![0u8; MAX_FRAME],
+            received: 0,
+            errors: 0,
+            deadline: None,
+        })
+    }
+}
+
+impl PacketSource for Link {
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        loop {
+            match self.imp.recv(&mut self.buf, self.deadline) {
+                Ok(None) => return Ok(None),
+                Ok(Some(info)) => {
+                    self.received += 1;
+                    return Ok(Some(Frame {
+                        data: self.buf[..info.len].to_vec(),
+                        timestamp: info.timestamp,
+                        orig_len: info.orig_len,
+                    }));
+                }
+                Err(e) => {
+                    self.errors += 1;
+                    eprintln!("rx error: {}", e);
+                }
+            }
+        }
+    }
+
+    fn stats(&mut self) -> Stats {
+        Stats {
+            received: self.received,
+            dropped: self.imp.dropped(),
+            errors: self.errors,
+        }
+    }
+
+    fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+}
+
+/// Milliseconds left until `deadline`, rounded up, for the receive calls of
+/// the backends. No deadline means waiting forever, or -1. Returns `None` once
+/// the deadline has passed, or capture was interrupted by a signal.
+fn timeout_ms(deadline: Option<Instant>) -> Option<i32> {
+    if source::interrupted() {
+        return None;
+    }
+    let Some(deadline) = deadline else {
+        return Some(-1);
+    };
+    let left = deadline.checked_duration_since(Instant::now())?;
+    Some(i32::try_from(left.as_micros().div_ceil(1000)).unwrap_or(i32::MAX))
 }
@@ -0,0 +1,60 @@
+// Copyright 2023 Oxide Computer Company
+
+use super::{timeout_ms, RecvInfo};
+use anyhow::Result;
+use dlpi::{
+    sys::{dlpi_recvinfo_t, DLPI_PHYSADDR_MAX},
+    DlpiHandle,
+};
+use std::time::{Instant, SystemTime};
+
+pub struct Link {
+    handle: DlpiHandle,
+}
+
+impl Link {
+    pub fn open(name: &str) -> Result<Self> {
+        let p = dlpi::open(name, dlpi::sys::DLPI_RAW)?;
+        dlpi::bind(p, 0x86dd)?; //XXX ?
+        dlpi::promisc_on(p, dlpi::sys::DL_PROMISC_MULTI)?;
+        dlpi::promisc_on(p, dlpi::sys::DL_PROMISC_SAP)?;
+        dlpi::promisc_on(p, dlpi::sys::DL_PROMISC_PHYS)?;
+        Ok(Self { handle: p })
+    }
+
+    /// Receive the next frame into `buf`, or `None` if `deadline` passes
+    /// first.
+    pub fn recv(
+        &mut self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> Result<Option<RecvInfo>> {
+        let Some(timeout) = timeout_ms(deadline) else {
+            return Ok(None);
+        };
+        let mut src = [0u8; DLPI_PHYSADDR_MAX];
+        let mut recvinfo = dlpi_recvinfo_t::default();
+        let n = match dlpi::recv(
+            self.handle,
+            &mut src,
+            buf,
+            timeout,
+            Some(&mut recvinfo),
+        ) {
+            Ok((_, n)) => n,
+            // A receive timing out is how the deadline is noticed.
+            Err(_) if timeout_ms(deadline).is_none() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(RecvInfo {
+            len: n,
+            orig_len: recvinfo.dri_totmsglen.max(n),
+            timestamp: SystemTime::now(),
+        }))
+    }
+
+    /// DLPI does not report frames dropped by the kernel.
+    pub fn dropped(&mut self) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright 2026 Oxide Computer Company
+
+//! AF_PACKET capture using a TPACKET_V3 receive ring.
+//!
+//! The kernel fills fixed size blocks in a ring shared with userspace. Each
+//! block holds a variable number of frames and is handed back to the kernel
+//! once every frame in it has been consumed.
+
+use super::{timeout_ms, RecvInfo};
+use anyhow::{anyhow, Result};
+use std::ffi::CString;
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::sync::atomic::{fence, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+const BLOCK_SIZE: u32 = 1 << 20;
+const BLOCK_COUNT: u32 = 64;
+const FRAME_SIZE: u32 = 1 << 11;
+
+/// Hand partially filled blocks to userspace after this many milliseconds so
+/// frames on a quiet link are not held back.
+const BLOCK_TIMEOUT_MS: u32 = 10;
+
+pub struct Link {
+    fd: OwnedFd,
+    ring: *mut u8,
+    block: usize,
+    /// Frames left in the current block and the offset of the next one.
+    pending: Option<(u32, usize)>,
+    /// Drops reported by the kernel so far. The kernel resets its counters
+    /// each time they are read.
+    dropped: u64,
+}
+
+impl Link {
+    pub fn open(name: &str) -> Result<Self> {
+        let ifname = CString::new(name)?;
+        let ifindex = unsafe { libc::if_nametoindex(ifname.as_ptr()) };
+        if ifindex == 0 {
+            return Err(anyhow!("{name}: {}", Error::last_os_error()));
+        }
+
+        let proto = (libc::ETH_P_ALL as u16).to_be();
+        let fd = unsafe {
+            libc::socket(libc::AF_PACKET, libc::SOCK_RAW, proto.into())
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error().into());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let version = libc::tpacket_versions::TPACKET_V3 as libc::c_int;
+        setsockopt(&fd, libc::PACKET_VERSION, &version)?;
+        let req = libc::tpacket_req3 {
+            tp_block_size: BLOCK_SIZE,
+            tp_block_nr: BLOCK_COUNT,
+            tp_frame_size: FRAME_SIZE,
+            tp_frame_nr: BLOCK_SIZE / FRAME_SIZE * BLOCK_COUNT,
+            tp_retire_blk_tov: BLOCK_TIMEOUT_MS,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        setsockopt(&fd, libc::PACKET_RX_RING, &req)?;
+
+        let ring = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                ring_len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ring == libc::MAP_FAILED {
+            return Err(Error::last_os_error().into());
+        }
+        let link = Self {
+            fd,
+            ring: ring.cast(),
+            block: 0,
+            pending: None,
+            dropped: 0,
+        };
+
+        let mut sll: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        sll.sll_family = libc::AF_PACKET as u16;
+        sll.sll_protocol = proto;
+        sll.sll_ifindex = ifindex as libc::c_int;
+        let rc = unsafe {
+            libc::bind(
+                link.fd.as_raw_fd(),
+                (&sll as *const libc::sockaddr_ll).cast(),
+                size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            return Err(Error::last_os_error().into());
+        }
+
+        let mreq = libc::packet_mreq {
+            mr_ifindex: ifindex as libc::c_int,
+            mr_type: libc::PACKET_MR_PROMISC as u16,
+            mr_alen: 0,
+            mr_address: [0; 8],
+        };
+        setsockopt(&link.fd, libc::PACKET_ADD_MEMBERSHIP, &mreq)?;
+
+        Ok(link)
+    }
+
+    /// Receive the next frame into `buf`, or `None` if `deadline` passes
+    /// first.
+    pub fn recv(
+        &mut self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> Result<Option<RecvInfo>> {
+        loop {
+            let base = self.block_base();
+            match self.pending {
+                Some((0, _)) => self.release_block(),
+                Some((n, off)) => {
+                    let (hdr, data) = unsafe {
+                        let hdr =
+                            &*(base.add(off) as *const libc::tpacket3_hdr);
+                        let data = std::slice::from_raw_parts(
+                            base.add(off + usize::from(hdr.tp_mac)),
+                            hdr.tp_snaplen as usize,
+                        );
+                        (hdr, data)
+                    };
+                    let len = data.len().min(buf.len());
+                    buf[..len].copy_from_slice(&data[..len]);
+                    let info = RecvInfo {
+                        len,
+                        orig_len: hdr.tp_len as usize,
+                        timestamp: UNIX_EPOCH
+                            + Duration::new(hdr.tp_sec.into(), hdr.tp_nsec),
+                    };
+                    self.pending =
+                        Some((n - 1, off + hdr.tp_next_offset as usize));
+                    return Ok(Some(info));
+                }
+                None => {
+                    let desc = base as *const libc::tpacket_block_desc;
+                    let status = unsafe {
+                        ptr::read_volatile(ptr::addr_of!(
+                            (*desc).hdr.bh1.block_status
+                        ))
+                    };
+                    if status & libc::TP_STATUS_USER == 0 {
+                        if !self.wait(deadline)? {
+                            return Ok(None);
+                        }
+                        continue;
+                    }
+                    fence(Ordering::Acquire);
+                    let bh1 = unsafe { &(*desc).hdr.bh1 };
+                    self.pending =
+                        Some((bh1.num_pkts, bh1.offset_to_first_pkt as usize));
+                }
+            }
+        }
+    }
+
+    pub fn dropped(&mut self) -> Option<u64> {
+        let mut stats = libc::tpacket_stats_v3 {
+            tp_packets: 0,
+            tp_drops: 0,
+            tp_freeze_q_cnt: 0,
+        };
+        let mut len = size_of::<libc::tpacket_stats_v3>() as libc::socklen_t;
+        let rc = unsafe {
+            libc::getsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                libc::PACKET_STATISTICS,
+                (&mut stats as *mut libc::tpacket_stats_v3).cast(),
+                &mut len,
+            )
+        };
+        if rc < 0 {
+            return None;
+        }
+        self.dropped += u64::from(stats.tp_drops);
+        Some(self.dropped)
+    }
+
+    fn block_base(&self) -> *mut u8 {
+        unsafe { self.ring.add(self.block * BLOCK_SIZE as usize) }
+    }
+
+    /// Return the current block to the kernel and move on to the next one.
+    fn release_block(&mut self) {
+        let desc = self.block_base() as *mut libc::tpacket_block_desc;
+        fence(Ordering::Release);
+        unsafe {
+            ptr::write_volatile(
+                ptr::addr_of_mut!((*desc).hdr.bh1.block_status),
+                libc::TP_STATUS_KERNEL,
+            );
+        }
+        self.block = (self.block + 1) % BLOCK_COUNT as usize;
+        self.pending = None;
+    }
+
+    /// Wait for the kernel to hand over a block. Returns false if `deadline`
+    /// has passed.
+    fn wait(&self, deadline: Option<Instant>) -> Result<bool> {
+        let Some(timeout) = timeout_ms(deadline) else {
+            return Ok(false);
+        };
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN | libc::POLLERR,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pfd, 1, timeout) } < 0 {
+            let e = Error::last_os_error();
+            if e.kind() != ErrorKind::Interrupted {
+                return Err(e.into());
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ring.cast(), ring_len());
+        }
+    }
+}
+
+fn ring_len() -> usize {
+    BLOCK_SIZE as usize * BLOCK_COUNT as usize
+}
+
+fn setsockopt<T>(fd: &OwnedFd, opt: libc::c_int, val: &T) -> Result<()> {
+    let rc = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_PACKET,
+            opt,
+            (val as *const T).cast(),
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if rc < 0 {
+        return Err(Error::last_os_error().into());
+    }
+    Ok(())
+}
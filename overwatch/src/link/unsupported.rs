@@ -0,0 +1,25 @@
+// Copyright 2026 Oxide Computer Company
+
+use super::RecvInfo;
+use anyhow::{anyhow, Result};
+use std::time::Instant;
+
+pub enum Link {}
+
+impl Link {
+    pub fn open(_name: &str) -> Result<Self> {
+        Err(anyhow!("live capture is not supported on this platform"))
+    }
+
+    pub fn recv(
+        &mut self,
+        _buf: &mut [u8],
+        _deadline: Option<Instant>,
+    ) -> Result<Option<RecvInfo>> {
+        match *self {}
+    }
+
+    pub fn dropped(&mut self) -> Option<u64> {
+        match *self {}
+    }
+}
@@ -1,23 +1,38 @@
 // Copyright 2023 Oxide Computer Company
 
-#![allow(clippy::too_many_arguments)]
-
 use anyhow::Result;
 use clap::Parser;
+use overwatch::{cli, hex_read, pcap_read, snoop, source};
+use std::ptr;
 
-mod cli;
-mod dump;
-mod hex_read;
-mod link;
-mod snoop;
+extern "C" fn on_signal(_signal: libc::c_int) {
+    source::interrupt();
+}
 
-p4_macro::use_p4!(p4 = "p4/overwatch.p4", pipeline_name = "overwatch");
+/// Stop capture on SIGINT or SIGTERM rather than exiting, so that the
+/// summary is still reported. System calls are not restarted, which wakes a
+/// source blocked waiting for frames. The handler is reset once it runs, so
+/// a second signal exits right away should the first one be missed.
+fn stop_on_signal() -> Result<()> {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESETHAND;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        if unsafe { libc::sigaction(signal, &action, ptr::null_mut()) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+    Ok(())
+}
 
 fn main() -> Result<()> {
     let args = cli::Cli::parse();
+    stop_on_signal()?;
 
     match &args.command {
         cli::Command::Snoop(s) => snoop::run(s),
-        cli::Command::HexRead(hr) => hex_read::run(&hr.file),
+        cli::Command::HexRead(hr) => hex_read::run(hr),
+        cli::Command::PcapRead(pr) => pcap_read::run(pr),
     }
 }
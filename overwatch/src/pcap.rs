@@ -0,0 +1,815 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Minimal pcap and pcapng support for recording and reading captures.
+//!
+//! Only the blocks needed to produce a file Wireshark and tcpdump can open are
+//! written: a section header, a single Ethernet interface description and
+//! enhanced packet blocks. Reading accepts classic pcap files in either byte
+//! order with microsecond or nanosecond timestamps, and pcapng files with
+//! enhanced and simple packet blocks.
+
+use crate::source::{Frame, PacketSource, Stats};
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SHB_TYPE: u32 = 0x0A0D_0D0A;
+const IDB_TYPE: u32 = 0x0000_0001;
+const SPB_TYPE: u32 = 0x0000_0003;
+const EPB_TYPE: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+const PCAP_MAGIC_USEC: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NSEC: u32 = 0xA1B2_3C4D;
+
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+/// Largest frame accepted when reading a capture. Lengths come straight from
+/// the file, so they are checked before allocating.
+const MAX_CAPLEN: usize = 256 * 1024;
+
+/// Largest pcapng block accepted when reading, leaving room for the options
+/// that may follow a frame of [`MAX_CAPLEN`] bytes.
+const MAX_BLOCK_LEN: usize = MAX_CAPLEN + 64 * 1024;
+
+/// Ethernet link type as assigned by tcpdump.org.
+pub const LINKTYPE_ETHERNET: u16 = 1;
+
+/// Writes a single pcapng section with one interface to an underlying writer.
+pub struct PcapNgWriter<W: Write> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    /// Start a new section on `out` and describe the Ethernet interface
+    /// `ifname`. Timestamps are recorded with nanosecond resolution.
+    pub fn new(out: W, ifname: &str, snaplen: u32) -> Result<Self> {
+        let mut w = Self { out, written: 0 };
+        w.section_header()?;
+        w.interface_description(ifname, snaplen)?;
+        Ok(w)
+    }
+
+    /// Number of bytes written to the underlying writer so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Record a frame received at `ts`. The `orig_len` is the length of the
+    /// frame on the wire, which may exceed `data.len()` if it was truncated.
+    pub fn write_packet(
+        &mut self,
+        ts: SystemTime,
+        data: &[u8],
+        orig_len: usize,
+    ) -> Result<()> {
+        let ns = ts
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let mut body = Vec::with_capacity(20 + data.len() + 3);
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&((ns >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ns as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(orig_len as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        pad32(&mut body);
+        self.block(EPB_TYPE, &body)
+    }
+
+    /// Flush buffered blocks to the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn section_header(&mut self) -> Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length is not known up front.
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        option(&mut body, OPT_ENDOFOPT, &[]);
+        self.block(SHB_TYPE, &body)
+    }
+
+    fn interface_description(
+        &mut self,
+        ifname: &str,
+        snaplen: u32,
+    ) -> Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&snaplen.to_le_bytes());
+        option(&mut body, OPT_IF_NAME, ifname.as_bytes());
+        option(&mut body, OPT_IF_TSRESOL, &[9]);
+        option(&mut body, OPT_ENDOFOPT, &[]);
+        self.block(IDB_TYPE, &body)
+    }
+
+    fn block(&mut self, typ: u32, body: &[u8]) -> Result<()> {
+        let len = (body.len() + 12) as u32;
+        self.out.write_all(&typ.to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.out.write_all(body)?;
+        self.out.write_all(&len.to_le_bytes())?;
+        self.written += u64::from(len);
+        Ok(())
+    }
+}
+
+fn option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    pad32(buf);
+}
+
+fn pad32(buf: &mut Vec<u8>) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+/// Output file rotation settings.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rotation {
+    /// Start a new file once the current one reaches this many bytes.
+    pub size: Option<u64>,
+    /// Keep at most this many files, reusing the oldest one when exceeded.
+    pub count: Option<usize>,
+}
+
+/// A pcapng file writer that optionally rotates across a set of files.
+///
+/// Without rotation, frames are written to `path`. With rotation, files are
+/// named by inserting an index before the extension of `path`, e.g.
+/// `trace.0.pcapng`, `trace.1.pcapng`, and so on.
+pub struct CaptureWriter {
+    path: PathBuf,
+    ifname: String,
+    snaplen: u32,
+    rotation: Rotation,
+    index: usize,
+    current: PcapNgWriter<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+    pub fn create(
+        path: impl AsRef<Path>,
+        ifname: &str,
+        snaplen: u32,
+        rotation: Rotation,
+    ) -> Result<Self> {
+        if rotation.size == Some(0) || rotation.count == Some(0) {
+            return Err(anyhow!("rotation size and count must not be zero"));
+        }
+        let path = path.as_ref().to_path_buf();
+        let current = open(&file_name(&path, &rotation, 0), ifname, snaplen)?;
+        Ok(Self {
+            path,
+            ifname: ifname.to_owned(),
+            snaplen,
+            rotation,
+            index: 0,
+            current,
+        })
+    }
+
+    pub fn write_packet(
+        &mut self,
+        ts: SystemTime,
+        data: &[u8],
+        orig_len: usize,
+    ) -> Result<()> {
+        if let Some(limit) = self.rotation.size {
+            if self.current.written() >= limit {
+                self.rotate()?;
+            }
+        }
+        self.current.write_packet(ts, data, orig_len)?;
+        // Snoop runs until interrupted, so flush each frame to leave a
+        // complete file behind.
+        self.current.flush()
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        self.current.flush()?;
+        self.index += 1;
+        if let Some(count) = self.rotation.count {
+            self.index %= count;
+        }
+        let name = file_name(&self.path, &self.rotation, self.index);
+        self.current = open(&name, &self.ifname, self.snaplen)?;
+        Ok(())
+    }
+}
+
+fn open(
+    path: &Path,
+    ifname: &str,
+    snaplen: u32,
+) -> Result<PcapNgWriter<BufWriter<File>>> {
+    let f = File::create(path)?;
+    PcapNgWriter::new(BufWriter::new(f), ifname, snaplen)
+}
+
+fn file_name(path: &Path, rotation: &Rotation, index: usize) -> PathBuf {
+    if rotation.size.is_none() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.{index}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{index}"),
+    };
+    path.with_file_name(name)
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nanos: bool,
+        snaplen: usize,
+        linktype: u32,
+    },
+    PcapNg(Section),
+}
+
+struct Section {
+    big_endian: bool,
+    interfaces: Vec<Interface>,
+}
+
+struct Interface {
+    linktype: u16,
+    tsresol: u8,
+}
+
+/// Reads Ethernet frames from a pcap or pcapng stream. The format is detected
+/// from the leading magic number.
+pub struct CaptureReader<R: Read> {
+    input: R,
+    format: Format,
+    received: u64,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut input: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        let format = if u32::from_le_bytes(magic) == SHB_TYPE {
+            let mut len = [0u8; 4];
+            input.read_exact(&mut len)?;
+            let big_endian = read_section_header(&mut input, len)?;
+            Format::PcapNg(Section {
+                big_endian,
+                interfaces: Vec::new(),
+            })
+        } else {
+            let (big_endian, nanos) =
+                match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                    (PCAP_MAGIC_USEC, _) => (false, false),
+                    (PCAP_MAGIC_NSEC, _) => (false, true),
+                    (_, PCAP_MAGIC_USEC) => (true, false),
+                    (_, PCAP_MAGIC_NSEC) => (true, true),
+                    _ => return Err(anyhow!("not a pcap or pcapng file")),
+                };
+            let mut hdr = [0u8; 20];
+            input.read_exact(&mut hdr)?;
+            let snaplen = u32_at(&hdr, 12, big_endian) as usize;
+            let linktype = u32_at(&hdr, 16, big_endian);
+            Format::Pcap {
+                big_endian,
+                nanos,
+                snaplen,
+                linktype,
+            }
+        };
+        Ok(Self {
+            input,
+            format,
+            received: 0,
+        })
+    }
+}
+
+impl<R: Read> PacketSource for CaptureReader<R> {
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        let frame = match &mut self.format {
+            Format::Pcap {
+                big_endian,
+                nanos,
+                snaplen,
+                linktype,
+            } => next_pcap(
+                &mut self.input,
+                *big_endian,
+                *nanos,
+                *snaplen,
+                *linktype,
+            ),
+            Format::PcapNg(section) => section.next_packet(&mut self.input),
+        }?;
+        if frame.is_some() {
+            self.received += 1;
+        }
+        Ok(frame)
+    }
+
+    fn stats(&mut self) -> Stats {
+        Stats {
+            received: self.received,
+            dropped: None,
+            errors: 0,
+        }
+    }
+}
+
+fn next_pcap<R: Read>(
+    input: &mut R,
+    big_endian: bool,
+    nanos: bool,
+    snaplen: usize,
+    linktype: u32,
+) -> Result<Option<Frame>> {
+    let mut hdr = [0u8; 16];
+    if !read_or_eof(input, &mut hdr)? {
+        return Ok(None);
+    }
+    ethernet_only(linktype)?;
+    let secs = u32_at(&hdr, 0, big_endian);
+    let frac = u32_at(&hdr, 4, big_endian);
+    let caplen = u32_at(&hdr, 8, big_endian) as usize;
+    let orig_len = u32_at(&hdr, 12, big_endian) as usize;
+    // Some writers leave the snapshot length unset.
+    let limit = match snaplen {
+        0 => MAX_CAPLEN,
+        n => n.min(MAX_CAPLEN),
+    };
+    if caplen > limit {
+        return Err(anyhow!("bad pcap record length {caplen}"));
+    }
+    let mut data = vec![0u8; caplen];
+    input.read_exact(&mut data)?;
+    let frac = if nanos {
+        Duration::from_nanos(frac.into())
+    } else {
+        Duration::from_micros(frac.into())
+    };
+    let timestamp = UNIX_EPOCH + Duration::from_secs(secs.into()) + frac;
+    Ok(Some(Frame {
+        data,
+        timestamp,
+        orig_len,
+    }))
+}
+
+impl Section {
+    fn next_packet<R: Read>(&mut self, input: &mut R) -> Result<Option<Frame>> {
+        let Section {
+            big_endian,
+            interfaces,
+        } = self;
+        loop {
+            let mut hdr = [0u8; 8];
+            if !read_or_eof(input, &mut hdr)? {
+                return Ok(None);
+            }
+
+            // A new section may switch byte order and resets interfaces.
+            if u32::from_le_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]) == SHB_TYPE
+            {
+                let len = [hdr[4], hdr[5], hdr[6], hdr[7]];
+                *big_endian = read_section_header(input, len)?;
+                interfaces.clear();
+                continue;
+            }
+
+            let typ = u32_at(&hdr, 0, *big_endian);
+            let len = u32_at(&hdr, 4, *big_endian) as usize;
+            if len < 12 || !len.is_multiple_of(4) {
+                return Err(anyhow!("bad pcapng block length {len}"));
+            }
+            if !matches!(typ, IDB_TYPE | EPB_TYPE | SPB_TYPE) {
+                skip(input, len - 8)?;
+                continue;
+            }
+            if len > MAX_BLOCK_LEN {
+                return Err(anyhow!("bad pcapng block length {len}"));
+            }
+            let mut body = vec![0u8; len - 8];
+            input.read_exact(&mut body)?;
+            let body = &body[..len - 12];
+            let min = match typ {
+                IDB_TYPE => 8,
+                EPB_TYPE => 20,
+                SPB_TYPE => 4,
+                _ => 0,
+            };
+            if body.len() < min {
+                return Err(anyhow!("truncated pcapng block"));
+            }
+
+            match typ {
+                IDB_TYPE => {
+                    let linktype = u16_at(body, 0, *big_endian);
+                    let tsresol =
+                        find_option(body, 8, OPT_IF_TSRESOL, *big_endian)
+                            .and_then(|v| v.first().copied())
+                            .unwrap_or(6);
+                    interfaces.push(Interface { linktype, tsresol });
+                }
+                EPB_TYPE => {
+                    let id = u32_at(body, 0, *big_endian) as usize;
+                    let Some(ifx) = interfaces.get(id) else {
+                        return Err(anyhow!("unknown pcapng interface {id}"));
+                    };
+                    ethernet_only(ifx.linktype.into())?;
+                    let hi = u64::from(u32_at(body, 4, *big_endian));
+                    let lo = u64::from(u32_at(body, 8, *big_endian));
+                    let caplen = u32_at(body, 12, *big_endian) as usize;
+                    let orig_len = u32_at(body, 16, *big_endian) as usize;
+                    let Some(data) = body.get(20..20 + caplen) else {
+                        return Err(anyhow!("truncated pcapng packet block"));
+                    };
+                    return Ok(Some(Frame {
+                        data: data.to_vec(),
+                        timestamp: UNIX_EPOCH
+                            + ts_duration((hi << 32) | lo, ifx.tsresol),
+                        orig_len,
+                    }));
+                }
+                SPB_TYPE => {
+                    let Some(ifx) = interfaces.first() else {
+                        return Err(anyhow!("unknown pcapng interface 0"));
+                    };
+                    ethernet_only(ifx.linktype.into())?;
+                    let orig_len = u32_at(body, 0, *big_endian) as usize;
+                    let caplen = orig_len.min(body.len() - 4);
+                    // Simple packet blocks carry no timestamp.
+                    return Ok(Some(Frame {
+                        data: body[4..4 + caplen].to_vec(),
+                        timestamp: UNIX_EPOCH,
+                        orig_len,
+                    }));
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+fn ethernet_only(linktype: u32) -> Result<()> {
+    if linktype != u32::from(LINKTYPE_ETHERNET) {
+        return Err(anyhow!("unsupported link type {linktype}"));
+    }
+    Ok(())
+}
+
+/// Read the rest of a section header block whose type and length fields have
+/// already been consumed, returning whether the section is big endian.
+fn read_section_header<R: Read>(input: &mut R, len: [u8; 4]) -> Result<bool> {
+    let mut bom = [0u8; 4];
+    input.read_exact(&mut bom)?;
+    let big_endian = match u32::from_le_bytes(bom) {
+        BYTE_ORDER_MAGIC => false,
+        _ if u32::from_be_bytes(bom) == BYTE_ORDER_MAGIC => true,
+        _ => return Err(anyhow!("bad pcapng byte order magic")),
+    };
+    let len = if big_endian {
+        u32::from_be_bytes(len)
+    } else {
+        u32::from_le_bytes(len)
+    } as usize;
+    if len < 28 || !len.is_multiple_of(4) {
+        return Err(anyhow!("bad pcapng section header length {len}"));
+    }
+    skip(input, len - 12)?;
+    Ok(big_endian)
+}
+
+/// Discard the next `len` bytes of `input`.
+fn skip<R: Read>(input: &mut R, len: usize) -> Result<()> {
+    let n = io::copy(&mut input.take(len as u64), &mut io::sink())?;
+    if n < len as u64 {
+        return Err(anyhow!("truncated capture file"));
+    }
+    Ok(())
+}
+
+/// Fill `buf`, returning false if the input ended before any bytes were read.
+fn read_or_eof<R: Read>(input: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut n = 0;
+    while n < buf.len() {
+        match input.read(&mut buf[n..]) {
+            Ok(0) if n == 0 => return Ok(false),
+            Ok(0) => return Err(anyhow!("truncated capture file")),
+            Ok(m) => n += m,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+fn find_option(
+    body: &[u8],
+    mut off: usize,
+    code: u16,
+    big_endian: bool,
+) -> Option<&[u8]> {
+    while off + 4 <= body.len() {
+        let c = u16_at(body, off, big_endian);
+        let len = u16_at(body, off + 2, big_endian) as usize;
+        if c == OPT_ENDOFOPT {
+            return None;
+        }
+        let value = body.get(off + 4..off + 4 + len)?;
+        if c == code {
+            return Some(value);
+        }
+        off += 4 + len.next_multiple_of(4);
+    }
+    None
+}
+
+/// Convert a pcapng timestamp in units of the interface resolution to a
+/// duration. The high bit of the resolution selects a power of two rather
+/// than a power of ten.
+fn ts_duration(units: u64, tsresol: u8) -> Duration {
+    let exp = u32::from(tsresol & 0x7f);
+    let per_sec: u128 = if tsresol & 0x80 != 0 {
+        1u128.checked_shl(exp).unwrap_or(u128::MAX)
+    } else {
+        10u128.checked_pow(exp).unwrap_or(u128::MAX)
+    };
+    let nanos = u128::from(units) * 1_000_000_000 / per_sec;
+    Duration::from_nanos(nanos as u64)
+}
+
+fn u16_at(buf: &[u8], off: usize, big_endian: bool) -> u16 {
+    let b = [buf[off], buf[off + 1]];
+    if big_endian {
+        u16::from_be_bytes(b)
+    } else {
+        u16::from_le_bytes(b)
+    }
+}
+
+fn u32_at(buf: &[u8], off: usize, big_endian: bool) -> u32 {
+    let b = [buf[off], buf[off + 1], buf[off + 2], buf[off + 3]];
+    if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAME: &[u8] = &[0xa8, 0x40, 0x25, 0x00, 0x00, 0x01, 0x86, 0xdd];
+
+    fn u16_bytes(v: u16, big_endian: bool) -> [u8; 2] {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    }
+
+    fn u32_bytes(v: u32, big_endian: bool) -> [u8; 4] {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    }
+
+    /// A classic pcap file holding `FRAME` captured at 10 seconds and `frac`
+    /// microseconds or nanoseconds, with a 64 KiB snapshot length.
+    fn pcap(big_endian: bool, nanos: bool, frac: u32, caplen: u32) -> Vec<u8> {
+        let magic = if nanos {
+            PCAP_MAGIC_NSEC
+        } else {
+            PCAP_MAGIC_USEC
+        };
+        let mut f = Vec::new();
+        f.extend(u32_bytes(magic, big_endian));
+        f.extend(u16_bytes(2, big_endian));
+        f.extend(u16_bytes(4, big_endian));
+        f.extend([0; 8]);
+        f.extend(u32_bytes(65535, big_endian));
+        f.extend(u32_bytes(LINKTYPE_ETHERNET.into(), big_endian));
+        f.extend(u32_bytes(10, big_endian));
+        f.extend(u32_bytes(frac, big_endian));
+        f.extend(u32_bytes(caplen, big_endian));
+        f.extend(u32_bytes(100, big_endian));
+        f.extend(FRAME);
+        f
+    }
+
+    fn block(big_endian: bool, typ: u32, body: &[u8]) -> Vec<u8> {
+        let len = (body.len() + 12) as u32;
+        let mut b = Vec::new();
+        b.extend(u32_bytes(typ, big_endian));
+        b.extend(u32_bytes(len, big_endian));
+        b.extend(body);
+        b.extend(u32_bytes(len, big_endian));
+        b
+    }
+
+    fn shb(big_endian: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(u32_bytes(BYTE_ORDER_MAGIC, big_endian));
+        body.extend(u16_bytes(1, big_endian));
+        body.extend(u16_bytes(0, big_endian));
+        body.extend([0xff; 8]);
+        block(big_endian, SHB_TYPE, &body)
+    }
+
+    fn idb(big_endian: bool, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(u16_bytes(LINKTYPE_ETHERNET, big_endian));
+        body.extend([0; 2]);
+        body.extend(u32_bytes(0, big_endian));
+        if let Some(r) = tsresol {
+            body.extend(u16_bytes(OPT_IF_TSRESOL, big_endian));
+            body.extend(u16_bytes(1, big_endian));
+            body.extend([r, 0, 0, 0]);
+            body.extend([0; 4]);
+        }
+        block(big_endian, IDB_TYPE, &body)
+    }
+
+    fn epb(big_endian: bool, units: u64) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(u32_bytes(0, big_endian));
+        body.extend(u32_bytes((units >> 32) as u32, big_endian));
+        body.extend(u32_bytes(units as u32, big_endian));
+        body.extend(u32_bytes(FRAME.len() as u32, big_endian));
+        body.extend(u32_bytes(100, big_endian));
+        body.extend(FRAME);
+        block(big_endian, EPB_TYPE, &body)
+    }
+
+    fn read_all(file: &[u8]) -> Result<Vec<Frame>> {
+        let mut r = CaptureReader::new(file)?;
+        let mut frames = Vec::new();
+        while let Some(f) = r.next_frame()? {
+            frames.push(f);
+        }
+        Ok(frames)
+    }
+
+    fn since_epoch(f: &Frame) -> Duration {
+        f.timestamp.duration_since(UNIX_EPOCH).unwrap()
+    }
+
+    #[test]
+    fn pcap_byte_orders_and_resolutions() {
+        for big_endian in [false, true] {
+            let file = pcap(big_endian, false, 250_000, FRAME.len() as u32);
+            let frames = read_all(&file).unwrap();
+            assert_eq!(frames.len(), 1);
+            assert_eq!(frames[0].data, FRAME);
+            assert_eq!(frames[0].orig_len, 100);
+            assert_eq!(since_epoch(&frames[0]), Duration::from_millis(10_250));
+
+            let file = pcap(big_endian, true, 250_000, FRAME.len() as u32);
+            let frames = read_all(&file).unwrap();
+            assert_eq!(
+                since_epoch(&frames[0]),
+                Duration::from_micros(10_000_250)
+            );
+        }
+    }
+
+    #[test]
+    fn pcap_rejects_oversized_record() {
+        let file = pcap(false, false, 0, 65536);
+        assert!(read_all(&file).is_err());
+        let file = pcap(true, true, 0, u32::MAX);
+        assert!(read_all(&file).is_err());
+    }
+
+    #[test]
+    fn pcapng_blocks() {
+        for big_endian in [false, true] {
+            let mut file = shb(big_endian);
+            // Milliseconds.
+            file.extend(idb(big_endian, Some(3)));
+            file.extend(epb(big_endian, 1500));
+            // Unknown blocks are skipped.
+            file.extend(block(big_endian, 0x0bad, &[0; 8]));
+            let mut spb = Vec::new();
+            spb.extend(u32_bytes(6, big_endian));
+            spb.extend(&FRAME[..6]);
+            spb.extend([0; 2]);
+            file.extend(block(big_endian, SPB_TYPE, &spb));
+
+            let frames = read_all(&file).unwrap();
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[0].data, FRAME);
+            assert_eq!(frames[0].orig_len, 100);
+            assert_eq!(since_epoch(&frames[0]), Duration::from_millis(1500));
+            assert_eq!(frames[1].data, &FRAME[..6]);
+            assert_eq!(frames[1].orig_len, 6);
+            assert_eq!(frames[1].timestamp, UNIX_EPOCH);
+        }
+    }
+
+    #[test]
+    fn pcapng_new_section() {
+        let mut file = shb(false);
+        file.extend(idb(false, Some(3)));
+        file.extend(shb(true));
+        file.extend(idb(true, None));
+        file.extend(epb(true, 7));
+        let frames = read_all(&file).unwrap();
+        assert_eq!(since_epoch(&frames[0]), Duration::from_micros(7));
+    }
+
+    #[test]
+    fn pcapng_tsresol() {
+        // Microseconds when the option is absent.
+        let mut file = shb(false);
+        file.extend(idb(false, None));
+        file.extend(epb(false, 2_000_001));
+        let frames = read_all(&file).unwrap();
+        assert_eq!(since_epoch(&frames[0]), Duration::from_micros(2_000_001));
+
+        // Powers of two.
+        let mut file = shb(false);
+        file.extend(idb(false, Some(0x80 | 10)));
+        file.extend(epb(false, 3 * 1024 + 512));
+        let frames = read_all(&file).unwrap();
+        assert_eq!(since_epoch(&frames[0]), Duration::from_millis(3500));
+    }
+
+    #[test]
+    fn pcapng_rejects_oversized_block() {
+        let mut file = shb(false);
+        file.extend(idb(false, None));
+        file.extend(u32_bytes(EPB_TYPE, false));
+        file.extend(u32_bytes(0xffff_fff0, false));
+        assert!(read_all(&file).is_err());
+    }
+
+    #[test]
+    fn pcapng_unknown_interface() {
+        let mut file = shb(false);
+        file.extend(epb(false, 0));
+        assert!(read_all(&file).is_err());
+    }
+
+    #[test]
+    fn writer_round_trip() {
+        let ts = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let mut file = Vec::new();
+        let mut w = PcapNgWriter::new(&mut file, "lo", 9000).unwrap();
+        w.write_packet(ts, FRAME, 100).unwrap();
+        w.write_packet(ts + Duration::from_nanos(1), &FRAME[..5], 5)
+            .unwrap();
+        w.flush().unwrap();
+        let written = w.written();
+        assert_eq!(written, file.len() as u64);
+
+        let frames = read_all(&file).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data, FRAME);
+        assert_eq!(frames[0].orig_len, 100);
+        assert_eq!(frames[0].timestamp, ts);
+        assert_eq!(frames[1].data, &FRAME[..5]);
+        assert_eq!(frames[1].orig_len, 5);
+        assert_eq!(frames[1].timestamp, ts + Duration::from_nanos(1));
+    }
+
+    #[test]
+    fn writer_rejects_zero_rotation() {
+        let path = std::env::temp_dir().join("overwatch-zero.pcapng");
+        for rotation in [
+            Rotation {
+                size: Some(0),
+                count: None,
+            },
+            Rotation {
+                size: Some(1_000_000),
+                count: Some(0),
+            },
+        ] {
+            assert!(CaptureWriter::create(&path, "lo", 9000, rotation).is_err());
+        }
+        assert!(!path.exists());
+    }
+}
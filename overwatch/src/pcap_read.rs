@@ -0,0 +1,21 @@
+// Copyright 2026 Oxide Computer Company
+
+use crate::cli::PcapRead;
+use crate::follow::Follower;
+use crate::pcap::CaptureReader;
+use crate::source::{self, Output};
+use anyhow::Result;
+use std::io::BufReader;
+
+pub fn run(pr: &PcapRead) -> Result<()> {
+    let input = BufReader::new(source::open(&pr.file)?);
+    let mut reader = CaptureReader::new(input)?;
+    let out = Output {
+        hex: pr.hex,
+        writer: None,
+        follow: Follower::new(&pr.follow)?,
+        format: pr.output,
+        time: pr.time,
+    };
+    source::run(&mut reader, &pr.filter, &pr.stop, out)
+}
@@ -0,0 +1,53 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Filtering and decoding of frames, independent of where they come from and
+//! of how they are shown.
+
+use crate::cli::Filter;
+use crate::decode::DecodedPacket;
+use crate::filter::Program;
+use anyhow::Result;
+
+/// A compiled filter along with the checks that need decoded headers.
+pub struct Pipeline {
+    program: Program,
+    bad_checksum_only: bool,
+}
+
+/// A frame kept by a [`Pipeline`].
+pub struct Matched<'a> {
+    /// The alternative of the filter expression that kept the frame.
+    pub alternative: usize,
+    /// The decoded headers, one set per pipeline output.
+    pub packets: Vec<DecodedPacket<'a>>,
+}
+
+impl Pipeline {
+    /// Build a pipeline from the filter flags and expression in `cfg`. A
+    /// default [`Filter`] keeps every frame.
+    pub fn new(cfg: &Filter) -> Result<Self> {
+        Ok(Self {
+            program: Program::new(cfg)?,
+            bad_checksum_only: cfg.bad_checksum_only,
+        })
+    }
+
+    /// Run `frame` through the filter and decode it, returning `None` if the
+    /// filter drops it.
+    pub fn process<'a>(&mut self, frame: &'a [u8]) -> Option<Matched<'a>> {
+        let (alternative, hdrs) = self.program.process(frame)?;
+        let packets: Vec<DecodedPacket> = hdrs
+            .iter()
+            .map(|(h, _)| DecodedPacket::decode(h, frame))
+            .collect();
+        if self.bad_checksum_only
+            && !packets.iter().any(|p| p.checksums.is_bad())
+        {
+            return None;
+        }
+        Some(Matched {
+            alternative,
+            packets,
+        })
+    }
+}
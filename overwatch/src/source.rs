@@ -0,0 +1,235 @@
+// Copyright 2026 Oxide Computer Company
+
+//! Packet sources and the receive loop shared by every subcommand.
+//!
+//! A source only produces raw frames. Filtering, recording and rendering are
+//! handled by [`run`], so adding a new source does not require touching the
+//! pipeline or the dump code.
+
+use crate::cli::{Filter, OutputFormat, Stop, TimeFormat};
+use crate::dump;
+use crate::follow::Follower;
+use crate::json;
+use crate::pcap::CaptureWriter;
+use crate::pipeline::Pipeline;
+use anyhow::Result;
+use std::fs::File;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A frame produced by a [`PacketSource`].
+pub struct Frame {
+    pub data: Vec<u8>,
+    /// When the frame was captured.
+    pub timestamp: SystemTime,
+    /// Length of the frame on the wire, which may exceed `data.len()` if it
+    /// was truncated on capture.
+    pub orig_len: usize,
+}
+
+/// Counters reported by a [`PacketSource`].
+pub struct Stats {
+    /// Frames handed out by the source.
+    pub received: u64,
+    /// Frames lost before reaching the source, if the source can tell.
+    pub dropped: Option<u64>,
+    /// Receive errors the source recovered from.
+    pub errors: u64,
+}
+
+pub trait PacketSource {
+    /// Block until the next frame is available. Returns `None` once the source
+    /// is exhausted.
+    fn next_frame(&mut self) -> Result<Option<Frame>>;
+
+    fn stats(&mut self) -> Stats;
+
+    /// Stop waiting for frames once `deadline` passes, with [`next_frame`]
+    /// returning `None` from then on. Only sources that may block for long
+    /// need to implement this.
+    ///
+    /// [`next_frame`]: PacketSource::next_frame
+    fn set_deadline(&mut self, _deadline: Instant) {}
+}
+
+/// What to do with frames that pass the filter.
+pub struct Output {
+    /// Dump frames in hex format along with the decoded headers.
+    pub hex: bool,
+    /// Record frames to a capture file.
+    pub writer: Option<CaptureWriter>,
+    /// Reassemble TCP streams rather than showing frames.
+    pub follow: Option<Follower>,
+    pub format: OutputFormat,
+    /// Show capture times in text output.
+    pub time: Option<TimeFormat>,
+}
+
+/// Set by [`interrupt`] to stop [`run`] early.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Stop the current [`run`] as if its source were exhausted. This only sets
+/// a flag, so it may be called from a signal handler. A source blocked
+/// waiting for frames only notices once its wait is interrupted, as it is by
+/// a signal.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether the current [`run`] was interrupted. Sources that block treat
+/// this like a deadline that has passed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Open `path` for reading, with `-` meaning standard input.
+pub fn open(path: &str) -> Result<Box<dyn Read>> {
+    if path == "-" {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// Run every frame from `source` through the filter pipeline until the source
+/// is exhausted, a stop condition is met or [`interrupt`] is called, then
+/// report what was seen on standard error.
+pub fn run(
+    source: &mut dyn PacketSource,
+    filter: &Filter,
+    stop: &Stop,
+    mut out: Output,
+) -> Result<()> {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+    let mut pipeline = Pipeline::new(filter)?;
+    let mut clock = out.time.map(dump::Clock::new);
+    let deadline = stop
+        .duration
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+    if let Some(d) = deadline {
+        source.set_deadline(d);
+    }
+    let mut matched = 0u64;
+    let mut filtered = 0u64;
+    let mut bytes = 0u64;
+
+    let mut stdout = std::io::stdout().lock();
+    if out.format == OutputFormat::Text {
+        dump::sep();
+    }
+    loop {
+        if interrupted()
+            || stop.count.is_some_and(|n| matched >= n)
+            || deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            break;
+        }
+        let Some(f) = source.next_frame()? else {
+            break;
+        };
+        let Some(m) = pipeline.process(&f.data) else {
+            filtered += 1;
+            continue;
+        };
+        let len = f.data.len() as u64;
+        if stop.max_bytes.is_some_and(|max| bytes + len > max) {
+            break;
+        }
+        matched += 1;
+        bytes += len;
+        let time = clock.as_mut().map(|c| c.time(f.timestamp));
+        if let Some(w) = &mut out.writer {
+            w.write_packet(f.timestamp, &f.data, f.orig_len)?;
+        }
+        for p in &m.packets {
+            match (&mut out.follow, out.format) {
+                (Some(follow), _) => follow.frame(p, f.timestamp)?,
+                (None, OutputFormat::Text) => {
+                    dump::frame(p, time.as_deref(), out.hex)
+                }
+                (None, OutputFormat::Json) => json::frame(
+                    &mut stdout,
+                    p,
+                    f.timestamp,
+                    f.orig_len,
+                    m.alternative,
+                    out.hex,
+                )?,
+            }
+        }
+    }
+
+    let stats = source.stats();
+    eprint!(
+        "{} frames seen, {} matched, {} dropped by filter, {} rx errors",
+        stats.received, matched, filtered, stats.errors,
+    );
+    match stats.dropped {
+        Some(dropped) => eprintln!(", {} dropped by the kernel", dropped),
+        None => eprintln!(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A source of empty frames that interrupts the run after `interrupt_at`
+    /// frames.
+    struct Frames {
+        left: u64,
+        interrupt_at: Option<u64>,
+        received: u64,
+    }
+
+    impl PacketSource for Frames {
+        fn next_frame(&mut self) -> Result<Option<Frame>> {
+            if self.left == 0 {
+                return Ok(None);
+            }
+            self.left -= 1;
+            self.received += 1;
+            if self.interrupt_at == Some(self.received) {
+                interrupt();
+            }
+            Ok(Some(Frame {
+                data: Vec::new(),
+                timestamp: SystemTime::now(),
+                orig_len: 0,
+            }))
+        }
+
+        fn stats(&mut self) -> Stats {
+            Stats {
+                received: self.received,
+                dropped: None,
+                errors: 0,
+            }
+        }
+    }
+
+    fn received(frames: u64, interrupt_at: Option<u64>) -> u64 {
+        let mut source = Frames {
+            left: frames,
+            interrupt_at,
+            received: 0,
+        };
+        let out = Output {
+            hex: false,
+            writer: None,
+            follow: None,
+            format: OutputFormat::Json,
+            time: None,
+        };
+        run(&mut source, &Filter::default(), &Stop::default(), out).unwrap();
+        source.received
+    }
+
+    #[test]
+    fn interrupt_stops_the_current_run_only() {
+        assert_eq!(received(10, Some(3)), 3);
+        // The next run starts afresh.
+        assert_eq!(received(10, None), 10);
+    }
+}